@@ -7,14 +7,14 @@ use nvm::{
 fn bench(c: &mut Criterion) {
     use Leaf::*;
     for (i, x) in [
-        D("+", &F(1.), &F(1.)),
+        D("+", Box::new(F(1.)), Box::new(F(1.))),
         D(
             ".",
-            &Fun {
+            Box::new(Fun {
                 a: vec![("x", LeafType::F), ("y", LeafType::F)],
                 v: vec![A(vec![X("x"), X("y")])],
-            },
-            &M("!", &F(2.)),
+            }),
+            Box::new(M("!", Box::new(F(2.)))),
         ),
     ]
     .into_iter()
@@ -23,7 +23,7 @@ fn bench(c: &mut Criterion) {
         let mut m = Machine::new();
         let b = m.compile(&x).unwrap();
 
-        let mut vm = VM::new(&m.instrs, &m.blocks, &m.bodies);
+        let mut vm = VM::new(m.program());
         c.bench_function(&format!("bc {i}"), |ctx| {
             ctx.iter(|| vm.exe_block(b).unwrap())
         });
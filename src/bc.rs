@@ -1,17 +1,28 @@
-use std::fmt;
+use crate::sym::Syms;
+use std::{fmt, ops::Deref, sync::Arc};
 
 macro_rules! impl_obj_enum {
-    (($obj:ident, $objtype:ident) => { $( $n:ident($($t:ty),*) ),* $(,)* }) => {
+    (($obj:ident, $objtype:ident) => {
+        $( $n:ident $(($($t:ty),*))? ),* $(,)*
+    }) => {
         #[derive(Debug, Copy, Clone, PartialEq)]
         pub enum $obj {
-            $($n($($t),*)),*
+            $($n $(($($t),*))?),*
         }
 
-        #[derive(Debug, Copy, Clone, PartialEq)]
+        #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
         #[repr(u8)]
         pub enum $objtype {
             $($n),*
         }
+
+        impl $obj {
+            pub fn ty(&self) -> $objtype {
+                match self {
+                    $($obj::$n { .. } => $objtype::$n),*
+                }
+            }
+        }
     }
 }
 
@@ -21,16 +32,60 @@ impl_obj_enum!((Obj, ObjType) => {
     U(usize),
     T(usize),
     Fun(usize),
+    /* a function with some of its args filled in */
+    P(usize),
+    /* an interned symbol */
+    S(usize),
+    /* nil, the value of nothing */
+    N,
+    /* a coroutine */
+    Co(usize),
 });
 
+impl Obj {
+    /** 0N, the integer null. nothing else is ever usize::MAX: math and
+     * parsing that get there overflow to a float instead */
+    pub const NU: Obj = Obj::U(usize::MAX);
+    /** 0n, the float null */
+    pub const NF: Obj = Obj::F(f64::NAN);
+    /** the char null, a space */
+    pub const NC: Obj = Obj::C(' ');
+
+    /** the null of type t. numbers and chars have their own, and
+     * everything else gets nil */
+    pub fn null(t: ObjType) -> Obj {
+        match t {
+            ObjType::U => Obj::NU,
+            ObjType::F => Obj::NF,
+            ObjType::C => Obj::NC,
+            _ => Obj::N,
+        }
+    }
+
+    /** whether x is nil or the null of its type */
+    pub fn is_null(&self) -> bool {
+        match self {
+            Obj::F(x) => x.is_nan(),
+            Obj::N => true,
+            x => *x == Obj::null(x.ty()),
+        }
+    }
+}
+
 impl fmt::Display for Obj {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", match self {
             Obj::C(c) => c.to_string(),
+            Obj::U(usize::MAX) => "0N".to_string(),
+            Obj::F(x) if x.is_nan() => "0n".to_string(),
             Obj::U(x) => x.to_string(),
             Obj::F(x) => x.to_string(),
             Obj::T(x) => format!("[&{x}]"),
             Obj::Fun(x) => format!("{{&{x}}}"),
+            Obj::P(x) => format!("{{p&{x}}}"),
+            Obj::S(x) => format!("`&{x}"),
+            Obj::N => "nil".to_string(),
+            Obj::Co(x) => format!("{{co&{x}}}"),
         })
     }
 }
@@ -47,13 +102,49 @@ pub enum Instr {
     /** create a new label */
     Label(usize),
 
-    /* math */
+    /* math. integers stay integers where the answer is one, and
+     * anything mixed with a float is a float */
     AddF,
     SubF,
     MulF,
+    /** always a float, as in k */
     DivF,
     NegF,
     CmpF,
+    /** integer division, rounding down */
+    DivU,
+    /** the remainder of DivU */
+    ModU,
+
+    /* comparison and logic. these give 0 or 1 */
+    Lt,
+    Gt,
+    Eq,
+    /** the same type and value, all the way down. not atomic */
+    Match,
+    And,
+    Or,
+    Not,
+
+    /* lists and strings. strings are vectors of chars */
+    /** x,y: x then y. atoms count as one item vectors */
+    Cat,
+    /** #x: how many items x has */
+    Len,
+    /** n#x: the first n items of x */
+    Take,
+    /** n_x: x without its first n items */
+    Drop,
+    /** split string x at each sep */
+    Split,
+    /** join a vector of strings with sep between them */
+    Join,
+    Upper,
+    Lower,
+    /** $x: x as a string */
+    Str,
+    /** the number written in string x */
+    Parse,
 
     /* vectors & tables */
     /** pop x and make a table */
@@ -66,13 +157,90 @@ pub enum Instr {
     VecPush(usize),
     /** push the last value in a vec */
     VecLast(usize),
-    /** pop a value off a vec */
+    /** drop the last value in a vec */
     VecPop(usize),
 
+    /* dictionaries, the key/value half of a table. keys are the same
+     * key when they match, and every NaN is the same key */
+    /** keys!values: pop two vectors and pair them up */
+    Dict,
+    /** pop a dict, a key and a default. push the key's value, or the
+     * default if it has none */
+    Get,
+    /** pop a dict, a key and a value. set the key in place and push
+     * the dict back */
+    Put,
+    /** pop a dict and a key. remove the key in place and push the dict
+     * back */
+    Del,
+    /** pop a dict and a key. push 1 if the dict has it */
+    Has,
+    Keys,
+    Values,
+
+    /* indexing. a table with any pairs is a dict and is indexed by key,
+     * otherwise it's a vector and is indexed by position. a vector of
+     * indices gives a vector of results. indexing anything else calls
+     * it, as in k */
+    /** x@i: pop x and i and push x at i. past the end of a vector or a
+     * missing key is the null of the table's items */
+    Index,
+    /** x . i: pop x and a vector i, and index x by each item of i in
+     * turn, so m . (1 2) is row 1, column 2 */
+    IndexDeep,
+    /** @[x;i;f]: pop x, i and f, and push a copy of x with f applied to
+     * the items at i */
+    Amend,
+    /** x[i]:v: pop x, i and v, set x at i to v in place and push x back.
+     * a vector v is spread over a vector i */
+    Assign,
+
     /* functions */
     Apply0,
     Apply1,
+    Apply2,
+    /** f . x: pop f and a vector x, and call f with x's items */
     ApplyN,
+    /** pop a function and the args that aren't holes, and project it
+     * over n args. bit i of the mask is set if arg i is a hole */
+    Proj(usize, usize),
+
+    /* adverbs. each pops a function and then its args */
+    /** f' x: f of each item of x */
+    Each,
+    /** f/ x: fold x with f */
+    Over,
+    /** f\ x: fold x with f, keeping every step */
+    Scan,
+    /** f': x: f of each item of x and the one before it */
+    EachPrior,
+    /** x f\: y: f of each item of x and y */
+    EachLeft,
+    /** x f/: y: f of x and each item of y */
+    EachRight,
+
+    /* errors */
+    /** 'x: pop x and raise it */
+    Signal,
+    /** .[f;args;h]: pop f, a vector of args and a handler, and call f
+     * with the args. if that raises anything, the stack is put back as
+     * it was and h is called with the signalled value, or with the name
+     * of the error as a string */
+    Trap,
+
+    /** pop x and hand it to the host, suspending the vm until it's
+     * resumed with a value, which is pushed in x's place */
+    Yield,
+
+    /* coroutines */
+    /** pop f and push a coroutine that will run it */
+    Coro,
+    /** pop a coroutine and x, and run the coroutine until it yields or
+     * returns, pushing what it gave. x is passed to f the first time,
+     * and is the value of the yield after that */
+    Resume,
+    /** pop a coroutine and push its status as a symbol */
+    Status,
 
     /* stack */
     Dup,
@@ -82,11 +250,14 @@ pub enum Instr {
 
     /* control */
     Ret,
+    /** stop in the debugger, if there is one */
     Break,
+    /** do nothing */
     Nop,
     /** jump to immediate block x */
     Jmp(usize),
-    /** pop and jump if zero */
+    /** pop and, if zero, jump to immediate block x in place of the
+     * rest of the body */
     JmpZ(usize),
     /** jump to a label if zero */
     LJmpZ(usize),
@@ -120,21 +291,97 @@ impl Blk {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Body<'a> {
+pub struct Body {
     pub start: usize,
     pub vars: usize,
-    pub names: Vec<(&'a str, ObjType)>,
+    pub names: Vec<(Arc<str>, ObjType)>,
     pub export: Vec<bool>,
 }
 
-impl<'a> Body<'a> {
-    pub fn exported(&self) -> Vec<(&'a str, ObjType)> {
+impl Body {
+    pub fn exported(&self) -> Vec<(Arc<str>, ObjType)> {
         assert_eq!(self.names.len(), self.export.len());
         self.names
             .iter()
             .enumerate()
             .filter(|(i, _)| self.export[*i])
-            .map(|(_, x)| *x)
+            .map(|(_, x)| x.clone())
             .collect::<Vec<_>>()
     }
 }
+
+/** the instructions, blocks and bodies of a compiled program, and the
+ * names of the symbols its instructions push */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Code {
+    pub instrs: Vec<Instr>,
+    pub blocks: Vec<Blk>,
+    pub bodies: Vec<Body>,
+    pub syms: Syms,
+}
+
+/** a program a vm can own. clones share the code, and code is only ever
+ * added to the end, so a program grown from another extends it */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    code: Arc<Code>,
+    /** shared by a program and every one grown from it */
+    line: Arc<()>,
+}
+
+impl Program {
+    pub fn new(
+        instrs: impl Into<Vec<Instr>>,
+        blocks: impl Into<Vec<Blk>>,
+        bodies: impl Into<Vec<Body>>,
+    ) -> Self {
+        Self {
+            code: Arc::new(Code {
+                instrs: instrs.into(),
+                blocks: blocks.into(),
+                bodies: bodies.into(),
+                syms: Syms::default(),
+            }),
+            line: Arc::default(),
+        }
+    }
+
+    /** whether this is p, or p with more code after it. everything
+     * extends a program with no code */
+    pub fn extends(&self, p: &Program) -> bool {
+        let (x, y) = (&self.code, &p.code);
+        if y.instrs.is_empty() && y.blocks.is_empty() && y.bodies.is_empty() {
+            return true;
+        }
+        Arc::ptr_eq(&self.line, &p.line)
+            && x.instrs.len() >= y.instrs.len()
+            && x.blocks.len() >= y.blocks.len()
+            && x.bodies.len() >= y.bodies.len()
+            && x.syms.len() >= y.syms.len()
+    }
+
+    /** the code, to add more to the end of. it's copied whole first if
+     * a clone is sharing it, so growing the program a vm is running
+     * copies everything compiled so far, and the vm keeps the old copy
+     * until it loads the new one */
+    pub(crate) fn grow(&mut self) -> &mut Code {
+        Arc::make_mut(&mut self.code)
+    }
+
+    /** the same code, as a program that doesn't extend this one or any
+     * it was grown from */
+    pub fn fork(&self) -> Self {
+        Self {
+            code: self.code.clone(),
+            line: Arc::default(),
+        }
+    }
+}
+
+impl Deref for Program {
+    type Target = Code;
+
+    fn deref(&self) -> &Code {
+        &self.code
+    }
+}
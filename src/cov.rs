@@ -0,0 +1,113 @@
+/** bytecode coverage, built as a tracer */
+use crate::{
+    BodyIterator,
+    bc::{Instr, Obj},
+    trace::Tracer,
+    vm::VM,
+};
+use std::collections::{HashMap, HashSet};
+
+/** what ran. reports are in terms of the disassembly, since leaves
+ * don't carry source positions yet */
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    pub instrs: HashSet<usize>,
+    pub blocks: HashSet<usize>,
+    pub bodies: HashSet<usize>,
+    /** branch instr => (taken, fell through) */
+    pub branches: HashMap<usize, (bool, bool)>,
+}
+
+/** whether x is a branch */
+fn branch(x: &Instr) -> bool {
+    matches!(x, Instr::JmpZ(_) | Instr::LJmpZ(_) | Instr::LJmpNZ(_))
+}
+
+/** a line of the summary: how many of how many */
+fn ratio(name: &str, n: usize, of: usize) -> String {
+    let p = if of == 0 { 100. } else { 100. * n as f64 / of as f64 };
+    format!("{name:>8}: {n}/{of} ({p:.1}%)\n")
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /** every instruction that belongs to some body */
+    fn reachable(vm: &VM) -> HashSet<usize> {
+        vm.bodies()
+            .iter()
+            .flat_map(|b| {
+                BodyIterator::from(vm.code(), b)
+                    .enumerate()
+                    .map(|(i, _)| i + b.start)
+            })
+            .collect()
+    }
+
+    pub fn summary(&self, vm: &VM) -> String {
+        let all = Self::reachable(vm);
+        let branches = all
+            .iter()
+            .filter(|i| branch(&vm.code()[**i]))
+            .count();
+        let arms = self
+            .branches
+            .values()
+            .map(|(x, y)| *x as usize + *y as usize)
+            .sum();
+
+        ratio("instrs", self.instrs.intersection(&all).count(), all.len())
+            + &ratio("blocks", self.blocks.len(), vm.blocks().len())
+            + &ratio("bodies", self.bodies.len(), vm.bodies().len())
+            + &ratio("branches", arms, 2 * branches)
+    }
+
+    /** the disassembly of every body, marking what never ran with '!' */
+    pub fn report(&self, vm: &VM) -> String {
+        let mut s = self.summary(vm);
+        for (i, b) in vm.bodies().iter().enumerate() {
+            let hit = self.bodies.contains(&i);
+            s += &format!("{}body {i}\n", if hit { ' ' } else { '!' });
+            for (j, x) in BodyIterator::from(vm.code(), b).enumerate() {
+                let j = j + b.start;
+                let m = if self.instrs.contains(&j) { ' ' } else { '!' };
+                let arms = match self.branches.get(&j) {
+                    _ if !branch(x) => "",
+                    Some((true, true)) => "",
+                    Some((true, false)) => "  (never fell through)",
+                    Some((false, true)) => "  (never taken)",
+                    _ => "  (never reached)",
+                };
+                s += &format!("{m} {j:5}: {x:?}{arms}\n");
+            }
+        }
+        s
+    }
+}
+
+impl Tracer for Coverage {
+    fn instr(&mut self, vm: &VM, x: &Instr) {
+        let at = vm.loc();
+        let i = at.instr;
+        self.instrs.insert(i);
+        self.blocks.insert(at.blk);
+        self.bodies.insert(at.body);
+
+        if !branch(x) {
+            return;
+        }
+        let zero = match vm.stack().last() {
+            Some(Obj::F(n)) => *n == 0.,
+            Some(Obj::U(n)) => *n == 0,
+            _ => return,
+        };
+        let b = self.branches.entry(i).or_default();
+        if zero != matches!(x, Instr::LJmpNZ(_)) {
+            b.0 = true;
+        } else {
+            b.1 = true;
+        }
+    }
+}
@@ -0,0 +1,93 @@
+/** step debugging for the vm */
+use crate::{bc::Instr, err::Loc, vm::VM};
+use std::fmt;
+
+/** where to stop */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Break {
+    /** before executing instruction x */
+    Instr(usize),
+    /** before the first instruction of block x */
+    Blk(usize),
+}
+
+/** how to carry on after a stop */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Step {
+    /** run until the next breakpoint */
+    Continue,
+    /** stop at the very next instruction */
+    Into,
+    /** stop at the next instruction in this body, running calls through */
+    Over,
+    /** stop once the current body returns */
+    Out,
+    /** abort execution */
+    Quit,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Why {
+    Break,
+    Step,
+}
+
+/** what the vm was about to do when it stopped */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Stop {
+    pub at: Loc,
+    pub instr: Instr,
+    pub depth: usize,
+    pub why: Why,
+}
+
+pub trait Debugger {
+    /** called at each stop with the vm paused before s.instr. the
+     * breakpoints may be edited here */
+    fn stop(&mut self, vm: &VM, s: &Stop, breaks: &mut Vec<Break>) -> Step;
+}
+
+/** the debugger state a vm carries around */
+pub struct Dbg {
+    pub breaks: Vec<Break>,
+    pub step: Step,
+    /** the depth at which the last step was asked for */
+    pub depth: usize,
+    pub ui: Box<dyn Debugger>,
+}
+
+impl Dbg {
+    pub fn new(ui: Box<dyn Debugger>) -> Self {
+        Self {
+            breaks: Vec::new(),
+            step: Step::Continue,
+            depth: 0,
+            ui,
+        }
+    }
+
+    /** should we stop at `at`, given the start of the body it's in? */
+    pub fn why(&self, at: Loc, start: usize, depth: usize) -> Option<Why> {
+        let hit = self.breaks.iter().any(|b| match b {
+            Break::Instr(i) => *i == at.instr,
+            Break::Blk(b) => *b == at.blk && start == at.instr,
+        });
+        match self.step {
+            _ if hit => Some(Why::Break),
+            Step::Into => Some(Why::Step),
+            Step::Over if depth <= self.depth => Some(Why::Step),
+            Step::Out if depth < self.depth => Some(Why::Step),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for Dbg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Dbg")
+            .field("breaks", &self.breaks)
+            .field("step", &self.step)
+            .field("depth", &self.depth)
+            .finish()
+    }
+}
@@ -0,0 +1,272 @@
+use crate::bc::{Instr, Obj, ObjType};
+use std::fmt;
+
+/** where an error happened: the instruction index and the block/body
+ * being executed (or, for the compiler, about to be emitted) */
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Loc {
+    pub instr: usize,
+    pub blk: usize,
+    pub body: usize,
+}
+
+impl fmt::Display for Loc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "instr {} (block {}, body {})",
+            self.instr, self.blk, self.body
+        )
+    }
+}
+
+/** something a vm can be limited in. see vm::Limits */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Limit {
+    Fuel,
+    Tables,
+    Elems,
+    Objs,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /** an operand had the wrong type */
+    Type {
+        expected: ObjType,
+        actual: ObjType,
+        at: Loc,
+    },
+    /** popped from an empty stack or vector */
+    Underflow { at: Loc },
+    /** nested too deeply */
+    Overflow { at: Loc },
+    /** put a table inside itself */
+    Cycle { at: Loc },
+    /** loaded a variable that was never set */
+    NoVar { var: usize, at: Loc },
+    /** referenced a block or body that doesn't exist */
+    NoBlock { blk: usize, at: Loc },
+    /** jumped to a label that doesn't exist */
+    NoLabel { label: usize, at: Loc },
+    /** referenced a table that doesn't exist */
+    NoTable { table: usize, at: Loc },
+    /** referenced a projection that doesn't exist */
+    NoProj { proj: usize, at: Loc },
+    /** referenced a coroutine that doesn't exist */
+    NoCoro { coro: usize, at: Loc },
+    /** called a block with the wrong number of arguments */
+    Arity {
+        expected: usize,
+        actual: usize,
+        at: Loc,
+    },
+    /** vectors of different lengths met in an atomic operation */
+    Length {
+        expected: usize,
+        actual: usize,
+        at: Loc,
+    },
+    /** indexed a vector past its end */
+    Index { index: usize, len: usize, at: Loc },
+    /** looked up a key a dict doesn't have */
+    NoKey { key: Obj, at: Loc },
+    /** a script raised value .0 with signal */
+    Signal { value: Obj, at: Loc },
+    /** ran out of one of the vm's limits */
+    Limit { limit: Limit, at: Loc },
+    /** yielded from inside a builtin's call, or from a run that can't be
+     * resumed */
+    Yield { at: Loc },
+    /** loaded a program into a vm that doesn't extend the one it had */
+    Reload { at: Loc },
+    /** resumed a vm or coroutine that isn't suspended, or started a vm
+     * that is */
+    Resume { at: Loc },
+    /** another thread cancelled execution */
+    Cancelled { at: Loc },
+    /** execution was stopped from outside */
+    Halted { at: Loc },
+    /** the vm can't execute this instruction */
+    Invalid { instr: Instr, at: Loc },
+    /** imported a name a unit doesn't define */
+    Missing {
+        unit: String,
+        name: String,
+        at: Loc,
+    },
+    /** imported a name a unit defines but doesn't export */
+    Private {
+        unit: String,
+        name: String,
+        at: Loc,
+    },
+    /** the frontend couldn't compile something */
+    Compile { msg: String, at: Loc },
+    /** a string that isn't a number was parsed as one */
+    Parse { text: String, at: Loc },
+    /** a snapshot couldn't be taken or restored */
+    Snapshot { msg: String, at: Loc },
+}
+
+impl Error {
+    pub fn loc(&self) -> Loc {
+        use Error::*;
+        match self {
+            Type { at, .. }
+            | Underflow { at }
+            | Overflow { at }
+            | Cycle { at }
+            | Halted { at }
+            | NoVar { at, .. }
+            | NoBlock { at, .. }
+            | NoLabel { at, .. }
+            | NoTable { at, .. }
+            | NoProj { at, .. }
+            | NoCoro { at, .. }
+            | Arity { at, .. }
+            | Length { at, .. }
+            | Index { at, .. }
+            | NoKey { at, .. }
+            | Signal { at, .. }
+            | Limit { at, .. }
+            | Cancelled { at }
+            | Yield { at }
+            | Resume { at }
+            | Reload { at }
+            | Invalid { at, .. }
+            | Missing { at, .. }
+            | Private { at, .. }
+            | Compile { at, .. }
+            | Parse { at, .. }
+            | Snapshot { at, .. } => *at,
+        }
+    }
+
+    /** a short name for what went wrong, as a trap's handler sees it */
+    pub fn kind(&self) -> &'static str {
+        use Error::*;
+        match self {
+            Type { .. } => "type",
+            Underflow { .. } => "underflow",
+            Overflow { .. } => "overflow",
+            Cycle { .. } => "cycle",
+            NoVar { .. } => "var",
+            NoBlock { .. } => "block",
+            NoLabel { .. } => "label",
+            NoTable { .. } => "table",
+            NoProj { .. } => "proj",
+            NoCoro { .. } => "coro",
+            Arity { .. } => "arity",
+            Length { .. } => "length",
+            Index { .. } => "index",
+            NoKey { .. } => "key",
+            Signal { .. } => "signal",
+            Limit {
+                limit: self::Limit::Fuel,
+                ..
+            } => "fuel",
+            Limit {
+                limit: self::Limit::Tables,
+                ..
+            } => "tables",
+            Limit {
+                limit: self::Limit::Elems,
+                ..
+            } => "elems",
+            Limit {
+                limit: self::Limit::Objs,
+                ..
+            } => "objs",
+            Cancelled { .. } => "cancelled",
+            Yield { .. } => "yield",
+            Resume { .. } => "resume",
+            Reload { .. } => "reload",
+            Halted { .. } => "halted",
+            Invalid { .. } => "invalid",
+            Missing { .. } => "missing",
+            Private { .. } => "private",
+            Compile { .. } => "compile",
+            Parse { .. } => "parse",
+            Snapshot { .. } => "snapshot",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Type {
+                expected,
+                actual,
+                at,
+            } => write!(
+                f,
+                "invalid operand at {at}: expected {expected:?}, got {actual:?}"
+            ),
+            Underflow { at } => write!(f, "stack underflow at {at}"),
+            Overflow { at } => write!(f, "nested too deeply at {at}"),
+            Cycle { at } => write!(f, "table would hold itself at {at}"),
+            NoVar { var, at } => write!(f, "no var {var} at {at}"),
+            NoBlock { blk, at } => write!(f, "no block {blk} at {at}"),
+            NoLabel { label, at } => {
+                write!(f, "label {label} not found at {at}")
+            }
+            NoTable { table, at } => {
+                write!(f, "table {table} not found at {at}")
+            }
+            NoProj { proj, at } => {
+                write!(f, "projection {proj} not found at {at}")
+            }
+            NoCoro { coro, at } => {
+                write!(f, "coroutine {coro} not found at {at}")
+            }
+            Arity {
+                expected,
+                actual,
+                at,
+            } => write!(
+                f,
+                "block called with {actual} args (expected {expected}) at {at}"
+            ),
+            Length {
+                expected,
+                actual,
+                at,
+            } => write!(
+                f,
+                "length {actual} doesn't match length {expected} at {at}"
+            ),
+            Index { index, len, at } => {
+                write!(f, "index {index} out of range for length {len} at {at}")
+            }
+            NoKey { key, at } => write!(f, "no key {key} at {at}"),
+            Signal { value, at } => write!(f, "signal {value} at {at}"),
+            Limit { at, .. } => write!(f, "out of {} at {at}", self.kind()),
+            Cancelled { at } => write!(f, "cancelled at {at}"),
+            Yield { at } => write!(f, "cannot yield from {at}"),
+            Resume { at } => write!(f, "nothing to resume at {at}"),
+            Reload { at } => {
+                write!(f, "program doesn't extend the vm's at {at}")
+            }
+            Halted { at } => write!(f, "halted at {at}"),
+            Invalid { instr, at } => {
+                write!(f, "invalid instruction {instr:?} at {at}")
+            }
+            Missing { unit, name, at } => {
+                write!(f, "unit {unit} has no binding {name} at {at}")
+            }
+            Private { unit, name, at } => {
+                write!(f, "{name} is not exported by unit {unit} at {at}")
+            }
+            Compile { msg, at } => write!(f, "compile error at {at}: {msg}"),
+            Parse { text, at } => {
+                write!(f, "cannot parse {text:?} as a number at {at}")
+            }
+            Snapshot { msg, at } => write!(f, "snapshot error at {at}: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
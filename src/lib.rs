@@ -1,17 +1,29 @@
-#![feature(let_chains)]
-
 pub mod bc;
+pub mod cov;
+pub mod dbg;
+pub mod err;
+pub mod link;
 pub mod lisp;
+pub mod prof;
+pub mod snap;
+pub mod sym;
+pub mod trace;
 pub mod vm;
 
 use crate::bc::{Body, Instr};
 
-pub type Res<T> = Result<T, String>;
+pub use crate::err::Error;
+
+pub type Res<T> = Result<T, Error>;
 
+/** make an Err(Error::$k) located at $at.loc() */
 #[macro_export]
-macro_rules! err_fmt {
-    ($($x:tt)*) => {{
-        Err(format!($($x)*))
+macro_rules! err {
+    ($at:expr, $k:ident) => {{
+        Err($crate::err::Error::$k { at: $at.loc() })
+    }};
+    ($at:expr, $k:ident { $($f:tt)* }) => {{
+        Err($crate::err::Error::$k { $($f)*, at: $at.loc() })
     }};
 }
 
@@ -75,7 +87,7 @@ pub struct BodyIterator<'a> {
 impl<'a> BodyIterator<'a> {
     pub fn from(src: &'a [Instr], b: &Body) -> Self {
         Self {
-            i: (&src[b.start..]).iter(),
+            i: src[b.start..].iter(),
         }
     }
 }
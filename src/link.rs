@@ -0,0 +1,126 @@
+/** links separately compiled machines into one program */
+use crate::{
+    Res,
+    bc::{Blk, Body, Instr, Obj},
+    err,
+    lisp::{Machine, Unit},
+};
+use std::collections::HashMap;
+
+/** how far each kind of index in one machine moves when linked */
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Reloc {
+    pub instr: usize,
+    pub blk: usize,
+    pub body: usize,
+    pub label: usize,
+    pub var: usize,
+}
+
+impl Reloc {
+    pub fn obj(&self, x: Obj) -> Obj {
+        match x {
+            Obj::Fun(b) => Obj::Fun(b + self.blk),
+            x => x,
+        }
+    }
+
+    pub fn instr(&self, x: Instr) -> Instr {
+        use Instr::*;
+        match x {
+            Push(x) => Push(self.obj(x)),
+            Local(v) => Local(v + self.var),
+            Load(v) => Load(v + self.var),
+            VecPush(v) => VecPush(v + self.var),
+            VecLast(v) => VecLast(v + self.var),
+            VecPop(v) => VecPop(v + self.var),
+            Label(l) => Label(l + self.label),
+            LJmpZ(l) => LJmpZ(l + self.label),
+            LJmpNZ(l) => LJmpNZ(l + self.label),
+            Jmp(b) => Jmp(b + self.blk),
+            JmpZ(b) => JmpZ(b + self.blk),
+            x => x,
+        }
+    }
+
+    pub fn blk(&self, x: Blk) -> Blk {
+        Blk(x.0, x.1, x.2 + self.body)
+    }
+
+    pub fn body(&self, x: &Body) -> Body {
+        Body {
+            start: x.start + self.instr,
+            ..x.clone()
+        }
+    }
+
+    pub fn unit(&self, x: &Unit) -> Unit {
+        Unit {
+            blk: x.blk + self.blk,
+            body: x.body + self.body,
+            vars: x.vars.iter().map(|v| v + self.var).collect(),
+        }
+    }
+}
+
+/** concatenate ms into one machine, relocating each one's indices past
+ * the ones before it and resolving imports between them by exported
+ * name. unit blocks keep their names, so `units[name].blk` is where to
+ * start each one */
+pub fn link(ms: &[&Machine]) -> Res<Machine> {
+    let mut r = Machine::new();
+    let mut externs = Vec::new();
+
+    for m in ms {
+        let o = Reloc {
+            instr: r.instrs.len(),
+            blk: r.blocks.len(),
+            body: r.bodies.len(),
+            label: r.labeln,
+            var: r.varn,
+        };
+
+        /* each machine interned its own symbols, so they're looked up
+         * again in the linked one's table */
+        let syms = (0..m.syms.len())
+            .map(|i| r.syms.intern(m.syms.name(i)))
+            .collect::<Vec<_>>();
+        r.instrs.extend(m.instrs.iter().map(|x| match o.instr(*x) {
+            Instr::Push(Obj::S(i)) if let Some(s) = syms.get(i) => {
+                Instr::Push(Obj::S(*s))
+            }
+            x => x,
+        }));
+        r.blocks.extend(m.blocks.iter().map(|x| o.blk(*x)));
+        r.bodies.extend(m.bodies.iter().map(|x| o.body(x)));
+        r.vars.extend(m.vars.iter().map(|(i, x)| (i + o.var, x.clone())));
+        for (n, u) in m.units.iter() {
+            if r.units.contains_key(n) {
+                return err!(r, Compile {
+                    msg: format!("unit {n} linked twice")
+                });
+            }
+            r.units.insert(n.clone(), o.unit(u));
+        }
+        externs.extend(m.externs.iter().map(|(v, u, n)| (v + o.var, u, n)));
+
+        r.varn += m.varn;
+        r.labeln += m.labeln;
+    }
+    r.scope = r.varn;
+
+    /* point every placeholder at the binding it names */
+    let mut to = HashMap::new();
+    for (v, u, n) in externs {
+        to.insert(v, r.import(u, n)?);
+    }
+    r.instrs.iter_mut().for_each(|x| {
+        if let Instr::Load(v) = x
+            && let Some(y) = to.get(v)
+        {
+            *x = Instr::Load(*y);
+        }
+    });
+
+    Ok(r)
+}
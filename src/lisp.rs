@@ -1,10 +1,19 @@
 /** a simple lisp frontend for testing before i write the k and lua */
 use crate::{
     BodyIterator, Res,
-    bc::{Blk, BlkType, Body, Instr, Obj, ObjType, Time},
-    err_fmt,
+    bc::{Blk, BlkType, Body, Code, Instr, Obj, ObjType, Program, Time},
+    err,
+    err::{Error, Loc},
 };
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::Arc,
+};
+
+/** how many leaves deep source may nest. reading and compiling recurse
+ * with fat frames, so this is well under the vm's MAX_DEPTH */
+pub const MAX_NEST: usize = 64;
 
 macro_rules! push {
     ($v:expr => [ $x:expr ]) => {{
@@ -22,14 +31,14 @@ macro_rules! push {
 macro_rules! set {
     ($v:expr => [ $x:expr ]) => {{
         let i = $v.len();
-        $v.insert($v.len(), $x);
+        $v.push($x);
         i
     }};
     ($v:expr => [ $($x:expr),* $(,)* ]) => {{
         let i = $v.len();
         $(
             if !$v.contains($x) {
-                $v.insert($v.len(), $x);
+                $v.push($x);
                 i
             } else {
                 $v.find(|x| x == $x).unwrap()
@@ -59,7 +68,7 @@ macro_rules! impl_math {
 
 macro_rules! impl_named_math {
     ($self:expr, var, $x:expr) => {{
-        Instr::Load($self.get_var_by_name($x))
+        Instr::Load($self.get_var_by_name($x)?)
     }};
     ($self:expr, flt, $x:expr) => {{
         Instr::Push(Obj::F(*$x))
@@ -85,69 +94,238 @@ macro_rules! impl_named_math {
 pub enum LeafType {
     C,
     F,
+    U,
+    S,
     M,
     D,
     A,
     Fun,
 }
 
-impl Into<ObjType> for LeafType {
-    fn into(self) -> ObjType {
+impl From<LeafType> for ObjType {
+    fn from(x: LeafType) -> Self {
         use LeafType::*;
-        match self {
+        match x {
             C => ObjType::C,
             F => ObjType::F,
-            _ => unreachable!(),
+            U => ObjType::U,
+            S => ObjType::S,
+            A => ObjType::T,
+            /* a monadic or dyadic verb is a function like any other */
+            M | D | Fun => ObjType::Fun,
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Leaf<'a> {
-    X(&'static str),
+    X(&'a str),
     F(f64),
+    U(usize),
+    /** a symbol, written `abc */
+    S(&'a str),
     C(char),
+    /** nil. the typed nulls 0N and 0n are read as U and F, so an
+     * integer literal of 0N's usize::MAX is read as a float */
+    Nil,
     A(Vec<Leaf<'a>>),
 
-    M(&'static str, &'a Leaf<'a>),
-    D(&'static str, &'a Leaf<'a>, &'a Leaf<'a>),
+    M(&'a str, Box<Leaf<'a>>),
+    D(&'a str, Box<Leaf<'a>>, Box<Leaf<'a>>),
+    /** a builtin verb of three args: (get d k default), or an if */
+    T(&'a str, Box<Leaf<'a>>, Box<Leaf<'a>>, Box<Leaf<'a>>),
     Fun {
-        a: Vec<(&'static str, LeafType)>,
+        a: Vec<(&'a str, LeafType)>,
         v: Vec<Leaf<'a>>,
     },
+
+    /** bind n to x. exported defs at the top of a unit can be imported */
+    Def {
+        n: &'a str,
+        x: Box<Leaf<'a>>,
+        export: bool,
+    },
+    /** bring binding .1 of unit .0 into scope */
+    Import(&'a str, &'a str),
+    /** a missing arg to a verb, which projects it: (f 1 _) */
+    Hole,
+    /** a builtin verb as a value: the + in (/ + x) */
+    V(&'a str),
+    /** adverb .0 on verb .1, applied to .2 and, for each-left and
+     * each-right, .3 */
+    Adv(&'a str, Box<Leaf<'a>>, Box<Leaf<'a>>, Option<Box<Leaf<'a>>>),
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct Machine<'a> {
-    pub vars: HashMap<usize, (&'static str, LeafType)>,
+/** the instruction behind builtin verb v, when it's used as a value */
+pub fn native(v: &str) -> Option<Instr> {
+    match v {
+        "+" => Some(Instr::AddF),
+        "-" => Some(Instr::SubF),
+        "*" => Some(Instr::MulF),
+        "%" => Some(Instr::DivF),
+        "div" => Some(Instr::DivU),
+        "mod" => Some(Instr::ModU),
+        "<" => Some(Instr::Lt),
+        ">" => Some(Instr::Gt),
+        "=" => Some(Instr::Eq),
+        "~" => Some(Instr::Match),
+        "&" => Some(Instr::And),
+        "|" => Some(Instr::Or),
+        "," => Some(Instr::Cat),
+        "#" => Some(Instr::Take),
+        "drop" => Some(Instr::Drop),
+        "split" => Some(Instr::Split),
+        "join" => Some(Instr::Join),
+        "!" => Some(Instr::Dict),
+        "del" => Some(Instr::Del),
+        "has" => Some(Instr::Has),
+        "resume" => Some(Instr::Resume),
+        "at" => Some(Instr::Index),
+        "dot" => Some(Instr::IndexDeep),
+        _ => None,
+    }
+}
+
+/** the instruction behind monadic builtin verb v */
+pub fn monad(v: &str) -> Option<Instr> {
+    match v {
+        "not" => Some(Instr::Not),
+        "#" => Some(Instr::Len),
+        "$" => Some(Instr::Str),
+        "num" => Some(Instr::Parse),
+        "upper" => Some(Instr::Upper),
+        "lower" => Some(Instr::Lower),
+        "keys" => Some(Instr::Keys),
+        "values" => Some(Instr::Values),
+        "signal" => Some(Instr::Signal),
+        "yield" => Some(Instr::Yield),
+        "coro" => Some(Instr::Coro),
+        "status" => Some(Instr::Status),
+        _ => None,
+    }
+}
+
+/** the instruction behind triadic builtin verb v */
+pub fn triad(v: &str) -> Option<Instr> {
+    match v {
+        "get" => Some(Instr::Get),
+        "put" => Some(Instr::Put),
+        "amend" => Some(Instr::Amend),
+        "assign" => Some(Instr::Assign),
+        "trap" => Some(Instr::Trap),
+        _ => None,
+    }
+}
+
+/** the instruction behind adverb a, and whether it takes two args */
+pub fn adverb(a: &str) -> Option<(Instr, bool)> {
+    match a {
+        "'" => Some((Instr::Each, false)),
+        "/" => Some((Instr::Over, false)),
+        "\\" => Some((Instr::Scan, false)),
+        "':" => Some((Instr::EachPrior, false)),
+        "\\:" => Some((Instr::EachLeft, true)),
+        "/:" => Some((Instr::EachRight, true)),
+        _ => None,
+    }
+}
+
+impl<'a> Leaf<'a> {
+    /** a best guess at what x evaluates to */
+    fn ty(&self) -> LeafType {
+        match self {
+            Leaf::C(_) => LeafType::C,
+            Leaf::U(_) => LeafType::U,
+            Leaf::S(_) => LeafType::S,
+            Leaf::A(_) | Leaf::M("!", _) | Leaf::D("!", ..) => LeafType::A,
+            Leaf::T("put" | "amend" | "assign", ..) => LeafType::A,
+            Leaf::Fun { .. } | Leaf::V(_) => LeafType::Fun,
+            Leaf::Adv(..) => LeafType::A,
+            Leaf::Def { x, .. } => x.ty(),
+            _ => LeafType::F,
+        }
+    }
+}
+
+/** a compiled unit: the block that runs it and the variable behind each
+ * of its body's names */
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Unit {
+    pub blk: usize,
+    pub body: usize,
+    pub vars: Vec<usize>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Machine {
+    pub vars: HashMap<usize, (Arc<str>, LeafType)>,
     pub varn: usize,
+    /** vars below this belong to units already compiled, and can only
+     * be reached by importing what they export */
+    pub scope: usize,
     pub labeln: usize,
-    pub instrs: Vec<Instr>,
-    pub blocks: Vec<Blk>,
-    pub bodies: Vec<Body<'a>>,
+    /** what's been compiled so far. vms share it, so it's only ever
+     * added to */
+    prog: Program,
+    pub units: HashMap<Arc<str>, Unit>,
+    /** imports from units this machine hasn't seen: (placeholder var,
+     * unit, name). the linker points them at the real binding */
+    pub externs: Vec<(usize, Arc<str>, Arc<str>)>,
+    /** how many leaves deep compile is */
+    depth: usize,
 }
 
-impl<'a> Machine<'a> {
-    pub fn new() -> Self {
+/* a clone goes on to compile code of its own, so the programs the two
+ * give out can't be loaded over each other */
+impl Clone for Machine {
+    fn clone(&self) -> Self {
         Self {
-            vars: HashMap::new(),
-            varn: 0,
-            labeln: 0,
-            instrs: Vec::new(),
-            blocks: Vec::new(),
-            bodies: Vec::new(),
+            vars: self.vars.clone(),
+            varn: self.varn,
+            scope: self.scope,
+            labeln: self.labeln,
+            prog: self.prog.fork(),
+            units: self.units.clone(),
+            externs: self.externs.clone(),
+            depth: self.depth,
         }
     }
+}
+
+impl Deref for Machine {
+    type Target = Code;
+
+    fn deref(&self) -> &Code {
+        &self.prog
+    }
+}
+
+/* writing copies the code first if a vm is running it */
+impl DerefMut for Machine {
+    fn deref_mut(&mut self) -> &mut Code {
+        self.prog.grow()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /** what's been compiled so far, as a program a vm can run */
+    pub fn program(&self) -> Program {
+        self.prog.clone()
+    }
 
     #[inline]
-    pub fn iter_body(&self, i: usize) -> BodyIterator {
+    pub fn iter_body(&self, i: usize) -> BodyIterator<'_> {
         BodyIterator::from(&self.instrs, &self.bodies[i])
     }
 
     #[inline]
-    fn add_var(&mut self, n: &'static str, t: LeafType) -> usize {
+    fn add_var(&mut self, n: &str, t: LeafType) -> usize {
         let i = self.varn;
-        self.vars.insert(i, (n, t));
+        self.vars.insert(i, (n.into(), t));
         self.varn += 1;
         i
     }
@@ -168,16 +346,31 @@ impl<'a> Machine<'a> {
             .filter(|(i, _)| i == &&x)
             .last()
             .map(|(i, _)| i)
-            .expect(&format!("{x} not defined"))
+            .unwrap_or_else(|| panic!("{x} not defined"))
     }
 
-    fn get_var_by_name(&self, x: &str) -> usize {
-        self.vars
+    fn get_var_by_name(&self, x: &str) -> Res<usize> {
+        /* the newest binding in scope wins */
+        match self
+            .vars
             .iter()
-            .filter(|(_, (n, _))| &x == n)
-            .last()
-            .map(|(i, _)| *i)
-            .expect(&format!("variable {x} undefined"))
+            .filter(|(i, (n, _))| **i >= self.scope && x == &**n)
+            .max_by_key(|(i, _)| **i)
+        {
+            Some((i, _)) => Ok(*i),
+            None => err!(self, Compile {
+                msg: format!("variable {x} undefined")
+            }),
+        }
+    }
+
+    /** where the next instruction, block and body will be emitted */
+    pub fn loc(&self) -> Loc {
+        Loc {
+            instr: self.instrs.len(),
+            blk: self.blocks.len(),
+            body: self.bodies.len(),
+        }
     }
 
     #[inline]
@@ -187,6 +380,85 @@ impl<'a> Machine<'a> {
         x
     }
 
+    /** the variable behind binding n of unit u, if u exports it */
+    pub(crate) fn import(&self, u: &str, n: &str) -> Res<usize> {
+        let Some(unit) = self.units.get(u) else {
+            return err!(self, Compile {
+                msg: format!("no unit named {u}")
+            });
+        };
+        let b = &self.bodies[unit.body];
+        match b.names.iter().rposition(|(x, _)| &**x == n) {
+            None => err!(self, Missing {
+                unit: u.to_string(),
+                name: n.to_string()
+            }),
+            Some(i) if !b.export[i] => err!(self, Private {
+                unit: u.to_string(),
+                name: n.to_string()
+            }),
+            Some(i) => Ok(unit.vars[i]),
+        }
+    }
+
+    /** the names unit u exports */
+    pub fn exports(&self, u: &str) -> Option<Vec<(Arc<str>, ObjType)>> {
+        self.units.get(u).map(|x| self.bodies[x.body].exported())
+    }
+
+    /** compile the leaves of unit `name` in order, returning the block
+     * that runs them. top level defs become the unit's bindings, and
+     * the unit returns the value of its last leaf. a unit can only
+     * import from units compiled before it, so running each unit's
+     * block in compile order satisfies every import */
+    pub fn unit(&mut self, name: &str, v: &[Leaf]) -> Res<usize> {
+        if self.units.contains_key(name) {
+            return err!(self, Compile {
+                msg: format!("unit {name} defined twice")
+            });
+        }
+        if v.is_empty() {
+            return err!(self, Compile {
+                msg: format!("unit {name} is empty")
+            });
+        }
+
+        self.scope = self.varn;
+        let mut ins = Vec::new();
+        let mut names = Vec::new();
+        let mut export = Vec::new();
+        let mut vars = Vec::new();
+        for (i, l) in v.iter().enumerate() {
+            let b = self.compile(l)?;
+            /* only the last leaf's value is kept */
+            if i > 0 {
+                ins.push(Instr::Pop);
+            }
+            ins.push(Instr::Jmp(b));
+
+            if let Leaf::Def { n, x, export: e } = l {
+                names.push(((*n).into(), x.ty().into()));
+                export.push(*e);
+                vars.push(self.get_var_by_name(n)?);
+            }
+        }
+        ins.push(Instr::Ret);
+
+        let i = self.instrs.len();
+        self.instrs.append(&mut ins);
+        let body = set!(self.bodies => [Body {
+            start: i,
+            vars: 0,
+            names,
+            export,
+        }]);
+        let blk =
+            set!(self.blocks => [Blk(BlkType::Fun, Time::Immediate, body)]);
+        self.units.insert(name.into(), Unit { blk, body, vars });
+        self.scope = self.varn;
+        Ok(blk)
+    }
+
     /*
      * ================================================
      * VERB IMPLS
@@ -199,7 +471,7 @@ impl<'a> Machine<'a> {
 
         /* push 0..x */
         (0..x).for_each(|x| {
-            self.instrs.push(Instr::Push(Obj::F(x as f64)));
+            self.instrs.push(Instr::Push(Obj::U(x)));
         });
         push!(self.instrs => [
             Instr::Vec(x),
@@ -215,32 +487,89 @@ impl<'a> Machine<'a> {
         Ok(set!(self.blocks => [Blk(BlkType::Fun, Time::Immediate, b)]))
     }
 
+    /** the instr that pushes x. functions are pushed rather than
+     * jumped to, so they don't get called with whatever's on the stack,
+     * and atoms and names don't need a block at all */
+    fn value(&mut self, x: &Leaf) -> Res<Instr> {
+        Ok(match x {
+            Leaf::F(x) => Instr::Push(Obj::F(*x)),
+            Leaf::U(x) => Instr::Push(Obj::U(*x)),
+            Leaf::C(x) => Instr::Push(Obj::C(*x)),
+            Leaf::S(x) => Instr::Push(Obj::S(self.syms.intern(x))),
+            Leaf::Nil => Instr::Push(Obj::N),
+            Leaf::X(x) => Instr::Load(self.get_var_by_name(x)?),
+            Leaf::Fun { .. } | Leaf::V(_) => {
+                Instr::Push(Obj::Fun(self.compile(x)?))
+            }
+            x => Instr::Jmp(self.compile(x)?),
+        })
+    }
+
+    /** a dyadic block running builtin verb v */
+    fn native(&mut self, v: &str) -> Res<usize> {
+        let Some(x) = native(v) else {
+            err!(self, Compile {
+                msg: format!("{v} is not a builtin verb")
+            })?
+        };
+        let i = push!(self.instrs => [x, Instr::Ret]);
+        let b = set!(self.bodies => [Body {
+            start: i,
+            vars: 2,
+            names: Vec::new(),
+            export: Vec::new(),
+        }]);
+        Ok(set!(self.blocks => [Blk(BlkType::Two, Time::Deferred, b)]))
+    }
+
+    /** call the verb f pushes with args, or project it if any of them
+     * are holes */
+    fn call(&mut self, f: Instr, args: &[&Leaf]) -> Res<usize> {
+        let mut ins = vec![f];
+        let mut holes = 0;
+        for (i, x) in args.iter().enumerate() {
+            match x {
+                Leaf::Hole => match u32::try_from(i)
+                    .ok()
+                    .and_then(|i| 1usize.checked_shl(i))
+                {
+                    Some(b) => holes |= b,
+                    None => err!(self, Compile {
+                        msg: "too many args to project".to_string()
+                    })?,
+                },
+                x => ins.push(self.value(x)?),
+            }
+        }
+        ins.push(match args.len() {
+            _ if holes != 0 => Instr::Proj(args.len(), holes),
+            1 => Instr::Apply1,
+            _ => Instr::Apply2,
+        });
+        Ok(self.immediate(&ins))
+    }
+
     /** apply1 x@y: apply y to x */
-    fn apply1(&mut self, x: &'a Leaf<'a>, y: &'a Leaf<'a>) -> Res<usize> {
+    fn apply1(&mut self, x: &Leaf, y: &Leaf) -> Res<usize> {
         let f = self.compile(x)?;
-        let g = self.compile(y)?;
-        Ok(self.immediate(&[
-            Instr::Push(Obj::Fun(f)),
-            Instr::Push(Obj::Fun(g)),
-            Instr::Apply0,
-            Instr::Apply1,
-        ]))
+        let g = self.value(y)?;
+        Ok(self.immediate(&[Instr::Push(Obj::Fun(f)), g, Instr::Apply1]))
     }
 
-    /** applyn x.y: push x applied to each y */
-    fn apply_n(&mut self, x: &'a Leaf<'a>, y: &'a [Leaf<'a>]) -> Res<usize> {
-        let f = self.compile(x)?;
+    /** applyn x.y: push x applied to the items of y */
+    fn apply_n(&mut self, x: &Leaf, y: &[Leaf]) -> Res<usize> {
+        let f = self.value(x)?;
         let mut ins = Vec::new();
 
-        /* we split off and compile each object in y while at the
-         * same time writing Jmp instrs to the ins vec, followed
-         * by the function and application */
-        for i in y.into_iter() {
-            let a = self.compile(i)?;
-            ins.push(Instr::Jmp(a));
+        /* we compile each object in y while at the same time writing
+         * them to the ins vec, then gather them into the vector of
+         * args ApplyN takes */
+        for i in y.iter() {
+            ins.push(self.value(i)?);
         }
         push!(ins => [
-            Instr::Push(Obj::Fun(f)),
+            Instr::Vec(y.len()),
+            f,
             Instr::ApplyN,
             Instr::Ret,
         ]);
@@ -256,19 +585,15 @@ impl<'a> Machine<'a> {
         Ok(set!(self.blocks => [Blk(BlkType::Fun, Time::Immediate, b)]))
     }
 
-    fn apply_n_to(&mut self, x: &'a Leaf<'a>, y: &'a Leaf<'a>) -> Res<usize> {
-        let m = self.compile(y)?;
-        let f = self.compile(x)?;
-        Ok(self.immediate(&[
-            Instr::Jmp(m),
-            Instr::PopVec,
-            Instr::Push(Obj::Fun(f)),
-            Instr::ApplyN,
-        ]))
+    fn apply_n_to(&mut self, x: &Leaf, y: &Leaf) -> Res<usize> {
+        let v = self.value(y)?;
+        let f = self.value(x)?;
+        Ok(self.immediate(&[v, f, Instr::ApplyN]))
     }
 
+    /** -x, except that -0 is 0 */
     fn negate(&mut self, x: f64) -> Res<usize> {
-        Ok(self.immediate(&[Instr::Push(Obj::F(-x))]))
+        Ok(self.immediate(&[Instr::Push(Obj::F(0. - x))]))
     }
 
     fn immediate(&mut self, v: &[Instr]) -> usize {
@@ -285,6 +610,7 @@ impl<'a> Machine<'a> {
         set!(self.blocks => [Blk(BlkType::Fun, Time::Immediate, b)])
     }
 
+    #[allow(unused)]
     fn lambda(&mut self, v: &[Instr]) -> usize {
         let i = self.instrs.len();
         v.iter().for_each(|x| self.instrs.push(*x));
@@ -301,29 +627,48 @@ impl<'a> Machine<'a> {
         set!(self.blocks => [Blk(BlkType::Fun, Time::Immediate, b)])
     }
 
-    pub fn compile(&mut self, l: &'a Leaf<'a>) -> Res<usize> {
+    /** compile l into a block, giving its index. leaves nested past
+     * MAX_NEST are an overflow rather than a blown rust stack */
+    pub fn compile(&mut self, l: &Leaf) -> Res<usize> {
+        if self.depth >= MAX_NEST {
+            return err!(self, Overflow);
+        }
+        self.depth += 1;
+        let r = self.compile_leaf(l);
+        self.depth -= 1;
+        r
+    }
+
+    fn compile_leaf(&mut self, l: &Leaf) -> Res<usize> {
         /* this giant mangled match statement compiles a block and
          * returns the index. then we just return it */
         let idx = match l {
             Leaf::X(x) => {
-                let i = self.get_var_by_name(x);
+                let i = self.get_var_by_name(x)?;
                 self.immediate(&[Instr::Load(i)])
             }
 
             Leaf::F(x) => self.immediate(&[Instr::Push(Obj::F(*x))]),
 
+            Leaf::U(x) => self.immediate(&[Instr::Push(Obj::U(*x))]),
+
+            Leaf::S(x) => {
+                let x = self.syms.intern(x);
+                self.immediate(&[Instr::Push(Obj::S(x))])
+            }
+
             Leaf::C(x) => self.immediate(&[Instr::Push(Obj::C(*x))]),
 
+            Leaf::Nil => self.immediate(&[Instr::Push(Obj::N)]),
+
             Leaf::A(x) => {
                 let mut v = Vec::new();
                 for x in x.iter() {
-                    v.push(self.compile(x)?);
+                    v.push(self.value(x)?);
                 }
 
                 let i = self.instrs.len();
-                v.iter().for_each(|x| {
-                    self.instrs.push(Instr::Jmp(*x));
-                });
+                self.instrs.extend_from_slice(&v);
                 push!(self.instrs => [
                     Instr::Vec(v.len()),
                     Instr::Ret,
@@ -338,28 +683,66 @@ impl<'a> Machine<'a> {
                 set!(self.blocks => [Blk(BlkType::Fun, Time::Immediate, b)])
             }
 
-            Leaf::D("+", Leaf::F(x), Leaf::F(y)) => {
+            /* x[1;] calls x with its elided args left as holes */
+            Leaf::D("dot", f, v)
+                if let Leaf::A(v) = &**v
+                    && v.contains(&Leaf::Hole) =>
+            {
+                let f = self.value(f)?;
+                self.call(f, &v.iter().collect::<Vec<_>>())?
+            }
+
+            /* builtins with a hole project like user verbs */
+            Leaf::D(v, x, y)
+                if native(v).is_some()
+                    && (**x == Leaf::Hole || **y == Leaf::Hole) =>
+            {
+                let f = self.native(v)?;
+                self.call(Instr::Push(Obj::Fun(f)), &[x, y])?
+            }
+
+            Leaf::D("+", x, y)
+                if let (Leaf::F(x), Leaf::F(y)) = (&**x, &**y) =>
+            {
                 impl_math!(self, (Obj::F(*x), Obj::F(*y)){Instr::AddF})
             }
-            Leaf::D("-", Leaf::F(x), Leaf::F(y)) => {
+            Leaf::D("-", x, y)
+                if let (Leaf::F(x), Leaf::F(y)) = (&**x, &**y) =>
+            {
                 impl_math!(self, (Obj::F(*x), Obj::F(*y)){Instr::SubF})
             }
-            Leaf::D("*", Leaf::F(x), Leaf::F(y)) => {
+            Leaf::D("*", x, y)
+                if let (Leaf::F(x), Leaf::F(y)) = (&**x, &**y) =>
+            {
                 impl_math!(self, (Obj::F(*x), Obj::F(*y)){Instr::MulF})
             }
-            Leaf::D("%", Leaf::F(x), Leaf::F(y)) => {
+            Leaf::D("%", x, y)
+                if let (Leaf::F(x), Leaf::F(y)) = (&**x, &**y) =>
+            {
                 impl_math!(self, (Obj::F(*x), Obj::F(*y)){Instr::DivF})
             }
 
-            Leaf::D("+", Leaf::F(x), Leaf::X(y)) => {
+            Leaf::D("+", x, y)
+                if let (Leaf::F(x), Leaf::X(y)) = (&**x, &**y) =>
+            {
                 impl_named_math!(self, ((flt x), (var y)){Instr::AddF})
             }
-            Leaf::D("+", Leaf::X(x), Leaf::F(y)) => {
+            Leaf::D("+", x, y)
+                if let (Leaf::X(x), Leaf::F(y)) = (&**x, &**y) =>
+            {
                 impl_named_math!(self, ((var x), (flt y)){Instr::AddF})
             }
-            Leaf::D("+", Leaf::X(x), Leaf::X(y)) => {
+            Leaf::D("+", x, y)
+                if let (Leaf::X(x), Leaf::X(y)) = (&**x, &**y) =>
+            {
                 impl_named_math!(self, ((var x), (var y)){Instr::AddF})
             }
+            /* anything else evaluates both sides first */
+            Leaf::D(v, x, y) if let Some(i) = native(v) => {
+                let x = self.value(x)?;
+                let y = self.value(y)?;
+                self.immediate(&[x, y, i])
+            }
 
             Leaf::Fun { a, v } => {
                 /* we'll write instrs to this vec for now so we can compile
@@ -375,7 +758,9 @@ impl<'a> Machine<'a> {
                 let addr = if let Some(x) = it.next() {
                     self.compile(x)?
                 } else {
-                    err_fmt!("no leaves in fun")?
+                    err!(self, Compile {
+                        msg: "no leaves in fun".to_string()
+                    })?
                 };
                 for x in it {
                     self.compile(x)?;
@@ -392,67 +777,121 @@ impl<'a> Machine<'a> {
                 let b = set!(self.bodies => [Body {
                     start: i,
                     vars: a.len(),
-                    names: a.into_iter()
-                        .map(|(n, x)| (*n, (*x).into()))
-                        .collect::<Vec<(&str, ObjType)>>(),
+                    names: a.iter()
+                        .map(|(n, x)| ((*n).into(), (*x).into()))
+                        .collect::<Vec<_>>(),
                     export: Vec::new(),
                 }]);
-                set!(self.blocks => [Blk(BlkType::Fun, Time::Deferred, b)])
+                let t = match a.len() {
+                    1 => BlkType::One,
+                    2 => BlkType::Two,
+                    _ => BlkType::Fun,
+                };
+                set!(self.blocks => [Blk(t, Time::Deferred, b)])
             }
 
-            Leaf::D("@", x @ Leaf::Fun { a: _, v: _ }, y) => {
+            Leaf::D("@", x, y) if matches!(**x, Leaf::Fun { .. }) => {
                 self.apply1(x, y)?
             }
+            /* anything else has to evaluate to a function first */
+            Leaf::D("@", x, y) => {
+                let f = self.compile(x)?;
+                let g = self.value(y)?;
+                self.immediate(&[Instr::Jmp(f), g, Instr::Apply1])
+            }
 
-            Leaf::D(".", x @ Leaf::Fun { a: _, v: _ }, Leaf::A(y)) => {
-                self.apply_n(x, &y)?
+            Leaf::Def { n, x, .. } => {
+                let b = self.value(x)?;
+                let v = self.add_var(n, x.ty());
+                self.immediate(&[b, Instr::Dup, Instr::Local(v)])
+            }
+            Leaf::Import(u, n) if !self.units.contains_key(*u) => {
+                let src = self.mk_var();
+                self.externs.push((src, (*u).into(), (*n).into()));
+                let v = self.add_var(n, LeafType::F);
+                self.immediate(&[Instr::Load(src), Instr::Dup, Instr::Local(v)])
+            }
+            Leaf::Import(u, n) => {
+                let src = self.import(u, n)?;
+                let t = self.vars.get(&src).map_or(LeafType::F, |x| x.1);
+                let v = self.add_var(n, t);
+                self.immediate(&[Instr::Load(src), Instr::Dup, Instr::Local(v)])
             }
-            Leaf::D(
-                ".",
-                x @ Leaf::Fun { a: _, v: _ },
-                y @ (Leaf::M(_, _) | Leaf::D(_, _, _)),
-            ) => self.apply_n_to(x, y)?,
 
-            Leaf::M("-", Leaf::F(x)) => self.negate(*x)?,
+            Leaf::D(".", x, y) if let Leaf::A(y) = &**y => self.apply_n(x, y)?,
+            Leaf::D(".", x, y) => self.apply_n_to(x, y)?,
+
+            Leaf::M("-", x) if let Leaf::F(x) = **x => self.negate(x)?,
             Leaf::M("-", x) => {
                 let x = self.compile(x)?;
                 self.immediate(&[Instr::Jmp(x), Instr::NegF])
             }
 
-            Leaf::M("!", Leaf::F(x)) => self.iota(*x as usize)?,
+            Leaf::M(v, x) if let Some(i) = monad(v) => {
+                let x = self.value(x)?;
+                self.immediate(&[x, i])
+            }
+
+            Leaf::T("if", c, x, y) => {
+                let c = self.value(c)?;
+                let x = self.value(x)?;
+                let y = self.value(y)?;
+                let y = self.immediate(&[y]);
+                self.immediate(&[c, Instr::JmpZ(y), x])
+            }
+
+            Leaf::T(v, x, y, z) if let Some(i) = triad(v) => {
+                let x = self.value(x)?;
+                let y = self.value(y)?;
+                let z = self.value(z)?;
+                self.immediate(&[x, y, z, i])
+            }
+
+            Leaf::M("!", x) if let Leaf::U(x) = **x => self.iota(x)?,
+            Leaf::M("!", x) if let Leaf::F(x) = **x => self.iota(x as usize)?,
             Leaf::M("!", x) => {
                 let x = self.compile(x)?;
-                let l = self.mk_label();
-                let r = self.mk_var();
+                let (top, end) = (self.mk_label(), self.mk_label());
+                let (n, i, r) = (self.mk_var(), self.mk_var(), self.mk_var());
 
                 let i = push!(self.instrs => [
-                    /* jump to x, subtract one. this is the counter */
+                    /* jump to x. this is the bound */
                     Instr::Jmp(x),
-                    Instr::Push(Obj::F(1.)),
-                    Instr::SubF,
-
-                    /* make a singleton vector containing 0 */
-                    Instr::Push(Obj::F(0.)),
-                    Instr::Vec(1),
+                    Instr::Local(n),
 
-                    /* start a loop */
+                    /* start with an empty vector and a counter of 0 */
+                    Instr::Vec(0),
                     Instr::Local(r),
-                    Instr::Label(l),
-                    Instr::Dup,
-                    /* add 1 to the tail of the vec */
-                    Instr::VecLast(r),
-                    Instr::Push(Obj::F(1.)),
-                    Instr::AddF,
-                    Instr::Dup,
-                    /* push the result */
+                    Instr::Push(Obj::U(0)),
+                    Instr::Local(i),
+
+                    /* skip the loop if it's not under the bound to
+                     * begin with, so 0 and less give an empty vector */
+                    Instr::Load(i),
+                    Instr::Load(n),
+                    Instr::Lt,
+                    Instr::Not,
+                    Instr::LJmpNZ(end),
+
+                    /* push the counter and add 1 to it */
+                    Instr::Label(top),
+                    Instr::Load(i),
                     Instr::VecPush(r),
-                    /* now compare the result to the counter */
-                    Instr::CmpF,
-                    /* restart the loop if neq */
-                    Instr::LJmpNZ(l),
+                    Instr::Load(i),
+                    Instr::Push(Obj::U(1)),
+                    Instr::AddF,
+                    Instr::Local(i),
+
+                    /* restart the loop while it's under the bound */
+                    Instr::Load(i),
+                    Instr::Load(n),
+                    Instr::Lt,
+                    Instr::LJmpNZ(top),
 
                     /* return the vector */
+                    Instr::Label(end),
                     Instr::Load(r),
+                    Instr::Ret,
                 ]);
                 let b = set!(self.bodies => [Body {
                     start: i,
@@ -463,7 +902,35 @@ impl<'a> Machine<'a> {
                 set!(self.blocks => [Blk(BlkType::Fun, Time::Immediate, b)])
             }
 
-            x => err_fmt!("cannot compile leaf {x:?}")?,
+            Leaf::V(v) => self.native(v)?,
+
+            Leaf::Adv(a, f, x, y) => {
+                let Some((i, _)) = adverb(a) else {
+                    err!(self, Compile {
+                        msg: format!("{a} is not an adverb")
+                    })?
+                };
+                let mut ins = vec![self.value(f)?, self.value(x)?];
+                if let Some(y) = y {
+                    ins.push(self.value(y)?);
+                }
+                ins.push(i);
+                self.immediate(&ins)
+            }
+
+            /* user verbs, called by name */
+            Leaf::M(f, x) if self.get_var_by_name(f).is_ok() => {
+                let f = self.get_var_by_name(f)?;
+                self.call(Instr::Load(f), &[x])?
+            }
+            Leaf::D(f, x, y) if self.get_var_by_name(f).is_ok() => {
+                let f = self.get_var_by_name(f)?;
+                self.call(Instr::Load(f), &[x, y])?
+            }
+
+            x => err!(self, Compile {
+                msg: format!("cannot compile leaf {x:?}")
+            })?,
         };
 
         Ok(idx)
@@ -491,3 +958,308 @@ impl<'a> Machine<'a> {
             .for_each(|(n, (i, x))| println!("{i:3}: {n} = {x:?}"));
     }
 }
+
+/** an s-expression reader for Leaf trees:
+ *   1.5  x  'c'  "str"  `sym  nil  0N  0n  [a b c]  (verb x)  (verb x y)
+ *   (fn (x) ...)
+ * get, put, amend, assign and trap take three args: (get d k default)
+ * and so does (if c x y), which only runs the arm c picks. 0 is false
+ * a name followed by brackets indexes it: x[i] is (at x i), and
+ * x[i j] is (dot x [i j])
+ * a _ in place of an arg leaves a hole, projecting the verb. adverbs
+ * go at the head, and builtin verbs can be passed to them:
+ *   (' f x)  (/ + x)  (\ + x)  (': - x)  (\: f x y)  (/: f x y)
+ * leaves borrow their names from the source, and own their children */
+struct Reader<'s> {
+    src: &'s str,
+    pos: usize,
+    /** how many leaves deep it's reading */
+    depth: usize,
+}
+
+impl<'s> Reader<'s> {
+    fn err<T>(&self, msg: &str) -> Res<T> {
+        Err(Error::Compile {
+            msg: format!("{msg} at byte {}", self.pos),
+            at: Loc::default(),
+        })
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    /** skip whitespace and ; comments */
+    fn skip(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == ';' {
+                let n = self.src[self.pos..].find('\n');
+                self.pos = n.map_or(self.src.len(), |n| self.pos + n);
+            } else if c.is_whitespace() {
+                self.pos += c.len_utf8();
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Res<()> {
+        self.skip();
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            self.err(&format!("expected '{c}'"))
+        }
+    }
+
+    fn atom(&mut self) -> &'s str {
+        self.skip();
+        let s = &self.src[self.pos..];
+        let n = s
+            .find(|c: char| c.is_whitespace() || "()[];".contains(c))
+            .unwrap_or(s.len());
+        self.pos += n;
+        &s[..n]
+    }
+
+    /** read leaves until the closing bracket c */
+    fn until(&mut self, c: char) -> Res<Vec<Leaf<'s>>> {
+        let mut v = Vec::new();
+        loop {
+            self.skip();
+            match self.peek() {
+                Some(x) if x == c => {
+                    self.pos += 1;
+                    return Ok(v);
+                }
+                Some(_) => v.push(self.leaf()?),
+                None => return self.err(&format!("expected '{c}'")),
+            }
+        }
+    }
+
+    /** the args of x[...]. ; or whitespace separates them, and a slot
+     * left empty between ;s is elided, which projects x: f[1;] */
+    fn args(&mut self) -> Res<Vec<Leaf<'s>>> {
+        let (mut v, mut semi, mut slot) = (Vec::new(), false, false);
+        loop {
+            while let Some(c) = self.peek()
+                && c.is_whitespace()
+            {
+                self.pos += c.len_utf8();
+            }
+            match self.peek() {
+                Some(';') => {
+                    if !slot {
+                        v.push(Leaf::Hole);
+                    }
+                    self.pos += 1;
+                    (semi, slot) = (true, false);
+                }
+                Some(']') => {
+                    self.pos += 1;
+                    if semi && !slot {
+                        v.push(Leaf::Hole);
+                    }
+                    return Ok(v);
+                }
+                Some(_) => {
+                    v.push(self.leaf()?);
+                    slot = true;
+                }
+                None => return self.err("expected ']'"),
+            }
+        }
+    }
+
+    /** the next leaf. ones nested past MAX_NEST are an error rather
+     * than a blown rust stack */
+    fn leaf(&mut self) -> Res<Leaf<'s>> {
+        if self.depth >= MAX_NEST {
+            return self.err("nested too deeply");
+        }
+        self.depth += 1;
+        let r = self.next_leaf();
+        self.depth -= 1;
+        r
+    }
+
+    fn next_leaf(&mut self) -> Res<Leaf<'s>> {
+        self.skip();
+        match self.peek() {
+            None => self.err("unexpected end of input"),
+            Some('[') => {
+                self.pos += 1;
+                Ok(Leaf::A(self.until(']')?))
+            }
+            Some('(') => {
+                self.pos += 1;
+                self.form()
+            }
+            Some('"') => {
+                self.pos += 1;
+                let mut v = Vec::new();
+                let mut c = self.src[self.pos..].chars();
+                loop {
+                    let x = match c.next() {
+                        None => return self.err("unterminated string"),
+                        Some('"') => break,
+                        Some('\\') => match c.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(x) => x,
+                            None => return self.err("unterminated string"),
+                        },
+                        Some(x) => x,
+                    };
+                    v.push(Leaf::C(x));
+                }
+                self.pos = self.src.len() - c.as_str().len();
+                Ok(Leaf::A(v))
+            }
+            Some('\'') => {
+                let s = &self.src[self.pos + 1..];
+                let mut c = s.chars();
+                match (c.next(), c.next()) {
+                    (Some(x), Some('\'')) => {
+                        self.pos += 2 + x.len_utf8();
+                        Ok(Leaf::C(x))
+                    }
+                    _ => self.err("bad char literal"),
+                }
+            }
+            Some(_) => {
+                let a = self.atom();
+                let num = a.starts_with(|c: char| "-.".contains(c))
+                    || a.starts_with(|c: char| c.is_ascii_digit());
+                if a == "0N" {
+                    Ok(Leaf::U(usize::MAX))
+                } else if a == "0n" {
+                    Ok(Leaf::F(f64::NAN))
+                } else if a == "nil" {
+                    Ok(Leaf::Nil)
+                } else if num
+                    && let Ok(x) = a.parse::<usize>()
+                    && x != usize::MAX
+                {
+                    Ok(Leaf::U(x))
+                } else if num && let Ok(x) = a.parse::<f64>() {
+                    Ok(Leaf::F(x))
+                } else if let Some(x) = a.strip_prefix('`') {
+                    Ok(Leaf::S(x))
+                } else if a == "_" {
+                    Ok(Leaf::Hole)
+                } else if native(a).is_some() {
+                    Ok(Leaf::V(a))
+                } else if a.starts_with(|c: char| c.is_alphabetic()) {
+                    let mut x = Leaf::X(a);
+                    while self.peek() == Some('[') {
+                        self.pos += 1;
+                        let mut v = self.args()?;
+                        let (f, i) = match v.len() {
+                            1 => ("at", v.remove(0)),
+                            _ => ("dot", Leaf::A(v)),
+                        };
+                        x = Leaf::D(f, Box::new(x), Box::new(i));
+                    }
+                    Ok(x)
+                } else {
+                    self.err(&format!("unexpected '{a}'"))
+                }
+            }
+        }
+    }
+
+    /** the inside of (...) */
+    fn form(&mut self) -> Res<Leaf<'s>> {
+        let v = self.atom();
+        if let "def" | "export" | "import" = v {
+            let n = match self.atom() {
+                "" => return self.err("expected a name"),
+                x => x,
+            };
+            if v == "import" {
+                let x = match self.atom() {
+                    "" => return self.err("expected a name"),
+                    x => x,
+                };
+                self.expect(')')?;
+                return Ok(Leaf::Import(n, x));
+            }
+            let x = Box::new(self.leaf()?);
+            self.expect(')')?;
+            return Ok(Leaf::Def {
+                n,
+                x,
+                export: v == "export",
+            });
+        }
+
+        if v == "fn" {
+            self.expect('(')?;
+            let mut a = Vec::new();
+            loop {
+                self.skip();
+                if self.peek() == Some(')') {
+                    self.pos += 1;
+                    break;
+                }
+                match self.atom() {
+                    "" => return self.err("expected argument name"),
+                    x => a.push((x, LeafType::F)),
+                }
+            }
+            let v = self.until(')')?;
+            return Ok(Leaf::Fun { a, v });
+        }
+
+        if let Some((_, two)) = adverb(v) {
+            let f = Box::new(self.leaf()?);
+            let x = Box::new(self.leaf()?);
+            let y = if two { Some(Box::new(self.leaf()?)) } else { None };
+            self.expect(')')?;
+            return Ok(Leaf::Adv(v, f, x, y));
+        }
+
+        if v.is_empty() {
+            return self.err("expected verb");
+        }
+        let mut x = self.until(')')?.into_iter();
+        match (x.next(), x.next(), x.next(), x.next()) {
+            (Some(x), None, None, None) => Ok(Leaf::M(v, Box::new(x))),
+            (Some(x), Some(y), None, None) => {
+                Ok(Leaf::D(v, Box::new(x), Box::new(y)))
+            }
+            (Some(x), Some(y), Some(z), None)
+                if v == "if" || triad(v).is_some() =>
+            {
+                Ok(Leaf::T(v, Box::new(x), Box::new(y), Box::new(z)))
+            }
+            _ => self.err(&format!("verb {v} takes one or two arguments")),
+        }
+    }
+}
+
+/** read a single expression from src */
+pub fn read(src: &str) -> Res<Leaf<'_>> {
+    let mut r = Reader { src, pos: 0, depth: 0 };
+    let x = r.leaf()?;
+    r.skip();
+    match r.peek() {
+        None => Ok(x),
+        Some(_) => r.err("trailing input"),
+    }
+}
+
+/** read every expression in src, e.g. the leaves of a unit */
+pub fn read_all(src: &str) -> Res<Vec<Leaf<'_>>> {
+    let mut r = Reader { src, pos: 0, depth: 0 };
+    let mut v = Vec::new();
+    r.skip();
+    while r.peek().is_some() {
+        v.push(r.leaf()?);
+        r.skip();
+    }
+    Ok(v)
+}
@@ -1,3 +1,147 @@
+use nvm::{
+    cov::Coverage,
+    dbg::{Break, Debugger, Step, Stop},
+    fatal,
+    link::link,
+    lisp::{self, Machine},
+    or_fatal,
+    prof::Profiler,
+    trace::Pretty,
+    vm::VM,
+};
+use std::{
+    cell::RefCell,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+static USAGE: &str =
+    "usage: nvm (run|debug|trace|profile|fold|cover) <file>...";
+
+/** the interactive debugger behind `nvm debug` */
+struct Term;
+
+impl Term {
+    fn help() {
+        println!(
+            "s step  n next  o out  c continue  q quit\n\
+             b <i> break at instr  bb <i> break at block  \
+             d <i> delete break  l list breaks\n\
+             p stack  v vars  t tables  x disassemble"
+        );
+    }
+
+    fn disasm(vm: &VM, at: usize) {
+        let code = vm.code();
+        let lo = at.saturating_sub(4);
+        let hi = (at + 5).min(code.len());
+        for (i, x) in code[lo..hi].iter().enumerate() {
+            let i = i + lo;
+            println!("{} {i:4}: {x:?}", if i == at { "=>" } else { "  " });
+        }
+    }
+}
+
+impl Debugger for Term {
+    fn stop(&mut self, vm: &VM, s: &Stop, breaks: &mut Vec<Break>) -> Step {
+        println!("{:?} at {}: {:?}", s.why, s.at, s.instr);
+        let mut stdin = std::io::stdin().lock();
+        loop {
+            print!("(nvm) ");
+            let _ = std::io::stdout().flush();
+
+            let mut l = String::new();
+            match stdin.read_line(&mut l) {
+                Ok(0) | Err(_) => return Step::Quit,
+                Ok(_) => (),
+            }
+            let mut w = l.split_whitespace();
+            let cmd = w.next().unwrap_or("s");
+            let arg = w.next().and_then(|x| x.parse::<usize>().ok());
+
+            match (cmd, arg) {
+                ("s", _) => return Step::Into,
+                ("n", _) => return Step::Over,
+                ("o", _) => return Step::Out,
+                ("c", _) => return Step::Continue,
+                ("q", _) => return Step::Quit,
+                ("b", Some(i)) => breaks.push(Break::Instr(i)),
+                ("bb", Some(i)) => breaks.push(Break::Blk(i)),
+                ("d", Some(i)) if i < breaks.len() => {
+                    breaks.remove(i);
+                }
+                ("l", _) => breaks
+                    .iter()
+                    .enumerate()
+                    .for_each(|(i, b)| println!("{i:3}: {b:?}")),
+                ("p", _) => vm
+                    .stack()
+                    .iter()
+                    .rev()
+                    .enumerate()
+                    .for_each(|(i, x)| println!("{i:3}: {}", vm.fmt(x))),
+                ("v", _) => println!("{}", vm.fmt_vars()),
+                ("t", _) => println!("{}", vm.fmt_tables()),
+                ("x", _) => Term::disasm(vm, s.at.instr),
+                _ => Term::help(),
+            }
+        }
+    }
+}
+
 fn main() {
-    println!("Hello, world!");
+    let args = std::env::args().collect::<Vec<_>>();
+    let (cmd, paths) = match args.get(1) {
+        Some(c) if args.len() > 2 => (c.as_str(), &args[2..]),
+        _ => fatal!("{USAGE}"),
+    };
+
+    /* each file is compiled on its own as a unit named after it, then
+     * they're linked together and run in order */
+    let mut ms = Vec::new();
+    let mut names = Vec::new();
+    for p in paths {
+        let src = or_fatal(std::fs::read_to_string(p));
+        let v = or_fatal(lisp::read_all(&src));
+        let name = std::path::Path::new(p)
+            .file_stem()
+            .map_or(p.as_str(), |x| x.to_str().unwrap_or(p));
+
+        let mut m = Machine::new();
+        or_fatal(m.unit(name, &v));
+        ms.push(m);
+        names.push(name);
+    }
+    let m = or_fatal(link(&ms.iter().collect::<Vec<_>>()));
+    let units = names.iter().map(|x| m.units[*x].blk).collect::<Vec<_>>();
+
+    let p = Rc::new(RefCell::new(Profiler::new()));
+    let c = Rc::new(RefCell::new(Coverage::new()));
+    let mut vm = VM::new(m.program());
+    match cmd {
+        "run" => (),
+        "profile" | "fold" => vm.trace(p.clone()),
+        "cover" => vm.trace(c.clone()),
+        "trace" => vm.trace(Pretty::new(std::io::stderr())),
+        "debug" => {
+            vm.debug(Term);
+            vm.step(Step::Into);
+            Term::help();
+        }
+        _ => fatal!("{USAGE}"),
+    }
+
+    let r = units
+        .iter()
+        .map(|b| vm.exe_block(*b))
+        .collect::<Result<Vec<_>, _>>()
+        .map(|x| x[x.len() - 1]);
+    vm.untrace();
+    match (cmd, r) {
+        ("profile", Ok(_)) => print!("{}", p.borrow().report(&vm, 10)),
+        ("fold", Ok(_)) => println!("{}", p.borrow().fold()),
+        ("cover", Ok(_)) => print!("{}", c.borrow().report(&vm)),
+        (_, Ok(x)) => println!("{}", vm.fmt(&x)),
+        (_, Err(e)) => fatal!("{}", vm.report(&e)),
+    }
 }
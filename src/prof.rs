@@ -0,0 +1,168 @@
+/** a bytecode profiler, built as a tracer */
+use crate::{
+    BodyIterator, Res,
+    bc::{Instr, Obj},
+    trace::Tracer,
+    vm::VM,
+};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct Stat {
+    pub count: usize,
+    pub time: Duration,
+}
+
+impl Stat {
+    #[inline]
+    fn add(&mut self, t: Duration) {
+        self.count += 1;
+        self.time += t;
+    }
+}
+
+/** counts and times everything the vm runs. instruction times are
+ * self time; block and body times include everything they call.
+ * trace with an Rc<RefCell<Profiler>> to read it back afterwards */
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    pub instrs: HashMap<usize, Stat>,
+    pub blocks: HashMap<usize, Stat>,
+    pub bodies: HashMap<usize, Stat>,
+    /** (call site, callee block) => calls made by Apply* and Jmp */
+    pub calls: HashMap<(usize, usize), usize>,
+    /** block stack => self time spent with exactly that stack */
+    pub folded: HashMap<Vec<usize>, Duration>,
+    /** the blocks we're in and when we entered them */
+    stack: Vec<(usize, Option<usize>, Instant)>,
+    /** the instruction being timed */
+    last: Option<(usize, Instant)>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /** charge the time since the last instruction started to it */
+    fn lap(&mut self) -> Instant {
+        let now = Instant::now();
+        if let Some((i, t)) = self.last.take() {
+            let d = now - t;
+            self.instrs.entry(i).or_default().time += d;
+            let k = self.stack.iter().map(|x| x.0).collect::<Vec<_>>();
+            *self.folded.entry(k).or_default() += d;
+        }
+        now
+    }
+
+    /** bodies sorted by total time, hottest first */
+    pub fn hottest(&self) -> Vec<(usize, Stat)> {
+        let mut v = self
+            .bodies
+            .iter()
+            .map(|(i, x)| (*i, *x))
+            .collect::<Vec<_>>();
+        v.sort_by(|x, y| y.1.time.cmp(&x.1.time).then(x.0.cmp(&y.0)));
+        v
+    }
+
+    /** the n hottest bodies with their disassembly */
+    pub fn report(&self, vm: &VM, n: usize) -> String {
+        let mut s = String::new();
+        for (i, b) in self.hottest().into_iter().take(n) {
+            let Some(body) = vm.bodies().get(i) else {
+                continue;
+            };
+            s += &format!(
+                "body {i}: {} runs, {:?} total\n",
+                b.count, b.time
+            );
+            for (j, x) in BodyIterator::from(vm.code(), body).enumerate() {
+                let j = j + body.start;
+                let c = self.instrs.get(&j).copied().unwrap_or_default();
+                let calls = self
+                    .calls
+                    .iter()
+                    .filter(|((at, _), _)| *at == j)
+                    .map(|((_, b), n)| format!(" {n}x->{b}"))
+                    .collect::<String>();
+                s += &format!(
+                    "  {j:5}: {:8} {:>12?}  {x:?}{calls}\n",
+                    c.count, c.time
+                );
+            }
+        }
+        s
+    }
+
+    /** brendan gregg's folded stack format, for flamegraph.pl */
+    pub fn fold(&self) -> String {
+        let mut v = self
+            .folded
+            .iter()
+            .map(|(k, t)| {
+                let k = k
+                    .iter()
+                    .map(|x| format!("blk{x}"))
+                    .collect::<Vec<_>>()
+                    .join(";");
+                format!("{k} {}", t.as_nanos())
+            })
+            .collect::<Vec<_>>();
+        v.sort();
+        v.join("\n")
+    }
+}
+
+impl Tracer for Profiler {
+    fn instr(&mut self, vm: &VM, _: &Instr) {
+        let now = self.lap();
+        let i = vm.loc().instr;
+        self.instrs.entry(i).or_default().count += 1;
+        self.last = Some((i, now));
+    }
+
+    fn enter(&mut self, vm: &VM, blk: usize) {
+        let now = self.lap();
+        if !self.stack.is_empty() {
+            let at = vm.loc().instr;
+            if let Some(
+                Instr::Jmp(_)
+                | Instr::Apply0
+                | Instr::Apply1
+                | Instr::Apply2
+                | Instr::ApplyN
+                | Instr::Proj(..)
+                | Instr::Each
+                | Instr::Over
+                | Instr::Scan
+                | Instr::EachPrior
+                | Instr::EachLeft
+                | Instr::EachRight
+                | Instr::Index
+                | Instr::IndexDeep
+                | Instr::Amend
+                | Instr::Trap,
+            ) = vm.code().get(at)
+            {
+                *self.calls.entry((at, blk)).or_default() += 1;
+            }
+        }
+        let body = vm.blocks().get(blk).map(|x| x.idx());
+        self.stack.push((blk, body, now));
+    }
+
+    fn exit(&mut self, _: &VM, _: usize, _: &Res<Obj>) {
+        let now = self.lap();
+        if let Some((blk, body, t)) = self.stack.pop() {
+            self.blocks.entry(blk).or_default().add(now - t);
+            if let Some(b) = body {
+                self.bodies.entry(b).or_default().add(now - t);
+            }
+        }
+    }
+}
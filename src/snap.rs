@@ -0,0 +1,302 @@
+/** the byte format vm snapshots are written in */
+use crate::{
+    Res,
+    bc::{Blk, Code, Instr, Obj, ObjType},
+    err,
+    err::Loc,
+    sym::Syms,
+    vm::{Frame, Status},
+};
+use std::collections::HashMap;
+
+/** what every snapshot starts with. the last byte is the version */
+const MAGIC: &[u8; 4] = b"nvm\x02";
+
+/** the types an object's tag byte can be */
+const TYPES: [ObjType; 9] = [
+    ObjType::C,
+    ObjType::F,
+    ObjType::U,
+    ObjType::T,
+    ObjType::Fun,
+    ObjType::P,
+    ObjType::S,
+    ObjType::N,
+    ObjType::Co,
+];
+
+/** a hash of a program, so a snapshot can tell whether it's being loaded
+ * into the vm it was saved from. fnv-1a over the program written out as
+ * a snapshot would be, with symbols by name, so it doesn't matter what
+ * order they were interned in */
+pub fn fingerprint(c: &Code) -> u64 {
+    let mut w = Writer(Vec::new(), &c.syms);
+    for x in &c.instrs {
+        match x {
+            Instr::Push(x) => {
+                w.u8(0);
+                w.obj(*x);
+            }
+            /* nothing else holds an object, so its text is enough */
+            x => {
+                w.u8(1);
+                w.str(&format!("{x:?}"));
+            }
+        }
+    }
+    for Blk(t, time, b) in &c.blocks {
+        w.u8(*t as u8);
+        w.u8(*time as u8);
+        w.usize(*b);
+    }
+    for b in &c.bodies {
+        w.usize(b.start);
+        w.usize(b.vars);
+        w.usize(b.names.len());
+        for (n, t) in &b.names {
+            w.str(n);
+            w.u8(*t as u8);
+        }
+        b.export.iter().for_each(|x| w.u8(*x as u8));
+    }
+    w.0.iter().fold(0xcbf29ce484222325, |h, x| {
+        (h ^ *x as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/** little-endian numbers, with lengths before lists. symbols are written
+ * by their names in .1, since ids are only good for the table that
+ * interned them */
+#[derive(Debug, Clone)]
+pub struct Writer<'a>(pub Vec<u8>, pub &'a Syms);
+
+impl<'a> Writer<'a> {
+    /** start a snapshot of the program with fingerprint f and symbols
+     * syms */
+    pub fn new(f: u64, syms: &'a Syms) -> Self {
+        let mut w = Self(MAGIC.to_vec(), syms);
+        w.u64(f);
+        w
+    }
+
+    pub fn u8(&mut self, x: u8) {
+        self.0.push(x);
+    }
+
+    pub fn u64(&mut self, x: u64) {
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    pub fn usize(&mut self, x: usize) {
+        self.u64(x as u64);
+    }
+
+    pub fn obj(&mut self, x: Obj) {
+        self.u8(x.ty() as u8);
+        match x {
+            Obj::C(x) => self.u64(x as u64),
+            Obj::F(x) => self.u64(x.to_bits()),
+            Obj::U(x)
+            | Obj::T(x)
+            | Obj::Fun(x)
+            | Obj::P(x)
+            | Obj::Co(x) => self.usize(x),
+            Obj::S(x) => self.str(self.1.name(x)),
+            Obj::N => (),
+        }
+    }
+
+    pub fn str(&mut self, x: &str) {
+        self.usize(x.len());
+        self.0.extend_from_slice(x.as_bytes());
+    }
+
+    pub fn objs(&mut self, x: &[Obj]) {
+        self.usize(x.len());
+        x.iter().for_each(|x| self.obj(*x));
+    }
+
+    pub fn at(&mut self, x: Loc) {
+        self.usize(x.instr);
+        self.usize(x.blk);
+        self.usize(x.body);
+    }
+
+    pub fn frames(&mut self, x: &[Frame]) {
+        self.usize(x.len());
+        for f in x {
+            self.usize(f.blk);
+            self.usize(f.body);
+            self.usize(f.pc);
+            self.at(f.caller);
+        }
+    }
+
+    /** vars in id order, so the same vars always write the same bytes */
+    pub fn vars(&mut self, x: &HashMap<usize, Obj>) {
+        let mut v = x.iter().collect::<Vec<_>>();
+        v.sort_by_key(|x| x.0);
+        self.usize(v.len());
+        for (k, x) in v {
+            self.usize(*k);
+            self.obj(*x);
+        }
+    }
+
+    pub fn status(&mut self, x: Status) {
+        self.u8(match x {
+            Status::Ready => 0,
+            Status::Running => 1,
+            Status::Suspended => 2,
+            Status::Dead => 3,
+        });
+    }
+}
+
+/** reads back what a Writer wrote. symbols are looked up by name in
+ * syms, and never added to it */
+#[derive(Debug, Clone)]
+pub struct Reader<'a> {
+    b: &'a [u8],
+    pos: usize,
+    syms: &'a Syms,
+}
+
+impl<'a> Reader<'a> {
+    /** read the head of snapshot b, giving the fingerprint of the
+     * program it was saved from */
+    pub fn new(b: &'a [u8], syms: &'a Syms) -> Res<(Self, u64)> {
+        let mut r = Self { b, pos: 0, syms };
+        if r.take(MAGIC.len())? != MAGIC {
+            return r.bad("not a snapshot");
+        }
+        let f = r.u64()?;
+        Ok((r, f))
+    }
+
+    #[inline]
+    pub fn loc(&self) -> Loc {
+        Loc::default()
+    }
+
+    fn bad<T>(&self, msg: &str) -> Res<T> {
+        err!(self, Snapshot {
+            msg: format!("{msg} at byte {}", self.pos)
+        })
+    }
+
+    fn take(&mut self, n: usize) -> Res<&'a [u8]> {
+        match self.b.get(self.pos..self.pos.saturating_add(n)) {
+            Some(x) => {
+                self.pos += n;
+                Ok(x)
+            }
+            None => self.bad("ran out of bytes"),
+        }
+    }
+
+    /** fail unless everything has been read */
+    pub fn end(&self) -> Res<()> {
+        match self.pos == self.b.len() {
+            true => Ok(()),
+            false => self.bad("trailing bytes"),
+        }
+    }
+
+    pub fn u8(&mut self) -> Res<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u64(&mut self) -> Res<u64> {
+        let mut x = [0; 8];
+        x.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(x))
+    }
+
+    pub fn usize(&mut self) -> Res<usize> {
+        match usize::try_from(self.u64()?) {
+            Ok(x) => Ok(x),
+            Err(_) => self.bad("number too big"),
+        }
+    }
+
+    /** a length, which can't be more than the bytes left */
+    pub fn count(&mut self) -> Res<usize> {
+        match self.usize()? {
+            n if n > self.b.len() - self.pos => self.bad("bad length"),
+            n => Ok(n),
+        }
+    }
+
+    pub fn obj(&mut self) -> Res<Obj> {
+        let t = self.u8()?;
+        let Some(t) = TYPES.into_iter().find(|x| *x as u8 == t) else {
+            return self.bad("bad object");
+        };
+        Ok(match t {
+            ObjType::C => {
+                let x = self.u64()?;
+                match u32::try_from(x).ok().and_then(char::from_u32) {
+                    Some(x) => Obj::C(x),
+                    None => return self.bad("bad char"),
+                }
+            }
+            ObjType::F => Obj::F(f64::from_bits(self.u64()?)),
+            ObjType::U => Obj::U(self.usize()?),
+            ObjType::T => Obj::T(self.usize()?),
+            ObjType::Fun => Obj::Fun(self.usize()?),
+            ObjType::P => Obj::P(self.usize()?),
+            ObjType::Co => Obj::Co(self.usize()?),
+            ObjType::S => {
+                let n = self.count()?;
+                let x = std::str::from_utf8(self.take(n)?).ok();
+                match x.and_then(|x| self.syms.get(x)) {
+                    Some(x) => Obj::S(x),
+                    None => return self.bad("unknown symbol"),
+                }
+            }
+            ObjType::N => Obj::N,
+        })
+    }
+
+    pub fn objs(&mut self) -> Res<Vec<Obj>> {
+        (0..self.count()?).map(|_| self.obj()).collect()
+    }
+
+    pub fn at(&mut self) -> Res<Loc> {
+        Ok(Loc {
+            instr: self.usize()?,
+            blk: self.usize()?,
+            body: self.usize()?,
+        })
+    }
+
+    pub fn frames(&mut self) -> Res<Vec<Frame>> {
+        (0..self.count()?)
+            .map(|_| {
+                Ok(Frame {
+                    blk: self.usize()?,
+                    body: self.usize()?,
+                    pc: self.usize()?,
+                    caller: self.at()?,
+                })
+            })
+            .collect()
+    }
+
+    pub fn vars(&mut self) -> Res<HashMap<usize, Obj>> {
+        (0..self.count()?)
+            .map(|_| Ok((self.usize()?, self.obj()?)))
+            .collect()
+    }
+
+    pub fn status(&mut self) -> Res<Status> {
+        Ok(match self.u8()? {
+            0 => Status::Ready,
+            1 => Status::Running,
+            2 => Status::Suspended,
+            3 => Status::Dead,
+            _ => return self.bad("bad status"),
+        })
+    }
+}
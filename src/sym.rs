@@ -0,0 +1,60 @@
+/** interned symbols. each program carries the table for the symbols in
+ * its code, so comparing two is comparing two numbers, and the names go
+ * when the program does */
+use crate::vm::Status;
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Syms {
+    names: Vec<Arc<str>>,
+    ids: HashMap<Arc<str>, usize>,
+}
+
+/* every table starts with the statuses of coroutines, so the vm can
+ * give them as symbols without adding to it */
+impl Default for Syms {
+    fn default() -> Self {
+        let mut s = Self {
+            names: Vec::new(),
+            ids: HashMap::new(),
+        };
+        for x in Status::ALL {
+            s.intern(x.name());
+        }
+        s
+    }
+}
+
+impl Syms {
+    /** the symbol for s, made the first time it's asked for */
+    pub fn intern(&mut self, s: &str) -> usize {
+        if let Some(i) = self.ids.get(s) {
+            return *i;
+        }
+        let s: Arc<str> = Arc::from(s);
+        let i = self.names.len();
+        self.names.push(s.clone());
+        self.ids.insert(s, i);
+        i
+    }
+
+    /** the symbol for s, if there is one */
+    pub fn get(&self, s: &str) -> Option<usize> {
+        self.ids.get(s).copied()
+    }
+
+    /** the text of symbol i */
+    pub fn name(&self, i: usize) -> &str {
+        self.names.get(i).map_or("?", |x| x)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
@@ -0,0 +1,179 @@
+/** execution tracing hooks */
+use crate::{
+    Res,
+    bc::{Instr, Obj},
+    err::{Error, Loc},
+    vm::VM,
+};
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+/** called by the vm as it runs. every hook defaults to doing nothing.
+ * a vm without a tracer never calls any of this */
+pub trait Tracer {
+    /** about to execute instruction x */
+    fn instr(&mut self, _vm: &VM, _x: &Instr) {}
+
+    /** about to execute block blk */
+    fn enter(&mut self, _vm: &VM, _blk: usize) {}
+
+    /** block blk finished with r */
+    fn exit(&mut self, _vm: &VM, _blk: usize, _r: &Res<Obj>) {}
+
+    /** table t was allocated */
+    fn table(&mut self, _vm: &VM, _t: usize) {}
+
+    /** an error was raised. called once, where it happened */
+    fn error(&mut self, _vm: &VM, _e: &Error) {}
+}
+
+/** lets the caller keep a handle on a tracer the vm owns */
+impl<T: Tracer> Tracer for Rc<RefCell<T>> {
+    fn instr(&mut self, vm: &VM, x: &Instr) {
+        self.borrow_mut().instr(vm, x)
+    }
+
+    fn enter(&mut self, vm: &VM, blk: usize) {
+        self.borrow_mut().enter(vm, blk)
+    }
+
+    fn exit(&mut self, vm: &VM, blk: usize, r: &Res<Obj>) {
+        self.borrow_mut().exit(vm, blk, r)
+    }
+
+    fn table(&mut self, vm: &VM, t: usize) {
+        self.borrow_mut().table(vm, t)
+    }
+
+    fn error(&mut self, vm: &VM, e: &Error) {
+        self.borrow_mut().error(vm, e)
+    }
+}
+
+/** a human readable trace with colors */
+pub struct Pretty<W: Write> {
+    w: W,
+    /** width of the widest instruction, worked out on first use */
+    width: Option<usize>,
+}
+
+impl<W: Write> Pretty<W> {
+    pub fn new(w: W) -> Self {
+        Self { w, width: None }
+    }
+}
+
+impl<W: Write> Tracer for Pretty<W> {
+    fn instr(&mut self, vm: &VM, x: &Instr) {
+        let n = *self.width.get_or_insert_with(|| {
+            vm.code()
+                .iter()
+                .map(|x| format!("{x:?}").len())
+                .fold(0, |x, y| x.max(y))
+                + 2
+        });
+        let _ = writeln!(
+            self.w,
+            "instr: \x1b[0;35m{:>n$}\x1b[0;0m {:?}",
+            format!("{x:?}"),
+            vm.stack(),
+        );
+    }
+
+    fn enter(&mut self, vm: &VM, blk: usize) {
+        let _ = writeln!(
+            self.w,
+            "\x1b[0;34mentering block {blk} at depth {}\x1b[0;0m",
+            vm.depth()
+        );
+    }
+
+    fn exit(&mut self, vm: &VM, blk: usize, r: &Res<Obj>) {
+        if let Ok(x) = r {
+            let _ = writeln!(
+                self.w,
+                "\x1b[0;34mblock {blk} returned {}\x1b[0;0m",
+                vm.fmt(x)
+            );
+        }
+    }
+
+    fn table(&mut self, _: &VM, t: usize) {
+        let _ = writeln!(self.w, "\x1b[0;32mnew table {t}\x1b[0;0m");
+    }
+
+    fn error(&mut self, _: &VM, e: &Error) {
+        let _ = writeln!(self.w, "\x1b[0;31merror: {e}\x1b[0;0m");
+    }
+}
+
+/** one json object per line, for feeding to other tools */
+pub struct Json<W: Write> {
+    w: W,
+}
+
+impl<W: Write> Json<W> {
+    pub fn new(w: W) -> Self {
+        Self { w }
+    }
+
+    fn line(&mut self, ev: &str, at: Loc, depth: usize, rest: &str) {
+        let _ = writeln!(
+            self.w,
+            r#"{{"ev":"{ev}","instr":{},"blk":{},"body":{},"depth":{}{rest}}}"#,
+            at.instr, at.blk, at.body, depth,
+        );
+    }
+}
+
+/** quote x as a json string */
+pub fn json_str(x: &str) -> String {
+    let mut s = String::with_capacity(x.len() + 2);
+    s.push('"');
+    for c in x.chars() {
+        match c {
+            '"' => s.push_str("\\\""),
+            '\\' => s.push_str("\\\\"),
+            '\n' => s.push_str("\\n"),
+            c if (c as u32) < 0x20 => {
+                s.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => s.push(c),
+        }
+    }
+    s.push('"');
+    s
+}
+
+impl<W: Write> Tracer for Json<W> {
+    fn instr(&mut self, vm: &VM, x: &Instr) {
+        let rest = format!(
+            r#","op":{},"stack":{}"#,
+            json_str(&format!("{x:?}")),
+            vm.stack().len()
+        );
+        self.line("instr", vm.loc(), vm.depth(), &rest);
+    }
+
+    fn enter(&mut self, vm: &VM, blk: usize) {
+        let rest = format!(r#","to":{blk}"#);
+        self.line("enter", vm.loc(), vm.depth(), &rest);
+    }
+
+    fn exit(&mut self, vm: &VM, blk: usize, r: &Res<Obj>) {
+        let rest = match r {
+            Ok(x) => format!(r#","from":{blk},"ok":{}"#, json_str(&vm.fmt(x))),
+            Err(_) => format!(r#","from":{blk},"ok":null"#),
+        };
+        self.line("exit", vm.loc(), vm.depth(), &rest);
+    }
+
+    fn table(&mut self, vm: &VM, t: usize) {
+        let rest = format!(r#","table":{t}"#);
+        self.line("table", vm.loc(), vm.depth(), &rest);
+    }
+
+    fn error(&mut self, vm: &VM, e: &Error) {
+        let rest = format!(r#","err":{}"#, json_str(&e.to_string()));
+        self.line("error", e.loc(), vm.depth(), &rest);
+    }
+}
@@ -1,52 +1,321 @@
 use crate::{
     BodyIterator, Res,
-    bc::{Blk, BlkType, Body, Instr, Obj, Time},
-    dbgln, err_fmt, heredoc,
+    bc::{Blk, BlkType, Body, Instr, Obj, ObjType, Program, Time},
+    dbg::{Break, Dbg, Debugger, Step, Stop, Why},
+    dbgln, err,
+    err::{Error, Limit, Loc},
+    heredoc,
+    snap::{Reader, Writer, fingerprint},
+    sym::Syms,
+    trace::Tracer,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
 };
-use std::collections::HashMap;
 
-#[cfg(test)]
-use colored::Colorize;
+/** how many bodies deep execution may nest before failing with an
+ * overflow instead of blowing the rust stack */
+pub const MAX_DEPTH: usize = 256;
 
-#[cfg(test)]
-use pad::{Alignment, PadStr};
+/** how many builtins deep calls back into bytecode may nest. each is a
+ * run nested on the rust stack, which is much fatter than a frame */
+pub const MAX_RUNS: usize = 32;
+
+/** what a vm may use before it stops with an error. None is no limit */
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /** how many instructions may run */
+    pub fuel: Option<usize>,
+    /** how many tables may be live at once. going past this or elems
+     * frees the unreachable ones first, so a table a host kept from an
+     * earlier run, and didn't root, may be gone */
+    pub tables: Option<usize>,
+    /** how many pairs and vector items the live tables may hold */
+    pub elems: Option<usize>,
+    /** how many projections and coroutines may be live at once. going
+     * past this frees the unreachable ones first, as with tables */
+    pub objs: Option<usize>,
+    /** how many bodies deep execution may nest, and how many tables
+     * deep anything that walks nested ones may go */
+    pub depth: usize,
+    /** how many builtins deep calls back into bytecode may nest */
+    pub runs: usize,
+    /** execution stops once another thread sets this */
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: None,
+            tables: None,
+            elems: None,
+            objs: None,
+            depth: MAX_DEPTH,
+            runs: MAX_RUNS,
+            cancel: None,
+        }
+    }
+}
+
+/** call hook $f on the vm's tracer, if it has one */
+macro_rules! trace {
+    ($self:ident, $f:ident($($a:expr),*)) => {{
+        if let Some(mut t) = $self.tracer.take() {
+            t.$f($self, $($a),*);
+            $self.tracer = Some(t);
+        }
+    }};
+}
 
+/** pop each operand off the vm's stack, where `_` is any object and
+ * an ObjType variant unwraps that variant or fails with a type error */
 macro_rules! pop_assign {
-    ( $s:expr => ( $( $x:pat => $y:ident ),* $(,)*) ) => {{
-        ($(
-            if let Some($x) = $s.pop() {
-                $y
-            } else {
-                err_fmt!(
-                    "invalid operand in pop assignment: expected {} to be {}",
-                    stringify!($y),
-                    stringify!($x)
-                )?
-            }
-        ),*)
+    (@ $s:expr, _) => {{
+        $s.pop()?
+    }};
+    (@ $s:expr, $t:ident) => {{
+        match $s.pop()? {
+            Obj::$t(x) => x,
+            x => err!($s, Type {
+                expected: ObjType::$t,
+                actual: x.ty()
+            })?,
+        }
     }};
 
-    [$s:expr, $r:expr] => {{
-        ($r)
-            .map(|_| $s.pop().unwrap())
-            .collect::<Vec<_>>()
+    ( $s:expr => ( $( $t:tt ),* $(,)*) ) => {{
+        ($( pop_assign!(@ $s, $t) ),*)
+    }};
+
+    [$s:expr, $n:expr] => {{
+        match $s.stack.len().checked_sub($n) {
+            Some(i) => $s
+                .stack
+                .split_off(i)
+                .into_iter()
+                .rev()
+                .collect::<Vec<_>>(),
+            None => err!($s, Underflow)?,
+        }
     }};
 }
 
 macro_rules! impl_math {
-    ($self:expr, $p:path => ($x:path, $y:path) {$f:expr}) => {{
-        let (y, x) = pop_assign!($self.stack => (
-            $x(x) => x,
-            $y(x) => x
-        ));
-        $self.stack.push($p($f(x, y)));
+    ($self:expr, {$u:expr}, {$f:expr}) => {{
+        let (y, x) = pop_assign!($self => (_, _));
+        let r = $self.atomic2(x, y, &|x, y| Num($u, $f).apply(x, y), 0)?;
+        $self.stack.push(r);
     }};
 }
 
+/** an arithmetic op on integers and on floats. the integer one gives
+ * None when the answer isn't an integer, and the float one is used.
+ * a null in gives a null out: 0N for integers and 0n for floats. 0N
+ * is usize::MAX, so an answer of that has overflowed too */
+struct Num(fn(usize, usize) -> Option<usize>, fn(f64, f64) -> f64);
+
+impl Num {
+    fn apply(&self, x: Obj, y: Obj) -> Option<Obj> {
+        match (x, y) {
+            /* ops that never give integers give 0n instead */
+            (Obj::U(_), Obj::U(_)) if x.is_null() || y.is_null() => {
+                Some(self.0(1, 1).map_or(Obj::NF, |_| Obj::NU))
+            }
+            (Obj::U(a), Obj::U(b))
+                if let Some(r) = self.0(a, b)
+                    && r != usize::MAX =>
+            {
+                Some(Obj::U(r))
+            }
+            _ if num(x) && num(y) => Some(Obj::F(self.1(flt(x), flt(y)))),
+            _ => None,
+        }
+    }
+}
+
+#[inline]
+fn num(x: Obj) -> bool {
+    matches!(x, Obj::F(_) | Obj::U(_))
+}
+
+#[inline]
+fn flt(x: Obj) -> f64 {
+    match x {
+        Obj::U(usize::MAX) => f64::NAN,
+        Obj::U(x) => x as f64,
+        Obj::F(x) => x,
+        _ => f64::NAN,
+    }
+}
+
+#[inline]
+fn bool(x: bool) -> Obj {
+    Obj::U(x as usize)
+}
+
+/** whether x is less than y, if they can be compared. a null number is
+ * less than any other */
+fn lt(x: Obj, y: Obj, syms: &Syms) -> Option<bool> {
+    match (x, y) {
+        _ if num(x) && num(y) && (x.is_null() || y.is_null()) => {
+            Some(x.is_null() && !y.is_null())
+        }
+        (Obj::U(x), Obj::U(y)) => Some(x < y),
+        (Obj::C(x), Obj::C(y)) => Some(x < y),
+        (Obj::S(x), Obj::S(y)) => Some(syms.name(x) < syms.name(y)),
+        _ if num(x) && num(y) => Some(flt(x) < flt(y)),
+        _ => None,
+    }
+}
+
+/** x = y. numbers are equal across types, and so are their nulls.
+ * anything else has to be identical */
+fn eq(x: Obj, y: Obj) -> bool {
+    match (x, y) {
+        _ if num(x) && num(y) && (x.is_null() || y.is_null()) => {
+            x.is_null() && y.is_null()
+        }
+        (Obj::U(x), Obj::U(y)) => x == y,
+        _ if num(x) && num(y) => flt(x) == flt(y),
+        _ => x == y,
+    }
+}
+
+/** whether number x is nonzero */
+fn truth(x: Obj) -> Option<bool> {
+    match x {
+        Obj::U(x) => Some(x != 0),
+        Obj::F(x) => Some(x != 0.),
+        _ => None,
+    }
+}
+
+/** obj x as a dictionary key. two keys are the same when they have the
+ * same type and value, so 1 and 1.0 are different keys. floats are
+ * keyed by their bits, except that every NaN is one key and -0 is 0 */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Key(ObjType, u64);
+
+impl From<Obj> for Key {
+    fn from(x: Obj) -> Self {
+        let n = match x {
+            Obj::F(x) if x.is_nan() => f64::NAN.to_bits(),
+            Obj::F(0.) => 0,
+            Obj::F(x) => x.to_bits(),
+            Obj::C(x) => x as u64,
+            Obj::U(x)
+            | Obj::T(x)
+            | Obj::Fun(x)
+            | Obj::P(x)
+            | Obj::S(x)
+            | Obj::Co(x) => x as u64,
+            Obj::N => 0,
+        };
+        Key(x.ty(), n)
+    }
+}
+
+/** the key/value half of a table. pairs stay in the order they were
+ * first added, and the index finds a key's pair without a scan */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Dict {
+    pairs: Vec<(Obj, Obj)>,
+    idx: HashMap<Key, usize>,
+}
+
+impl Dict {
+    #[inline]
+    pub fn pairs(&self) -> &[(Obj, Obj)] {
+        &self.pairs
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    pub fn get(&self, k: Obj) -> Option<Obj> {
+        self.idx.get(&k.into()).map(|i| self.pairs[*i].1)
+    }
+
+    /** set k to v, keeping k's place if it's already there */
+    pub fn insert(&mut self, k: Obj, v: Obj) {
+        match self.idx.get(&k.into()) {
+            Some(i) => self.pairs[*i].1 = v,
+            None => {
+                self.idx.insert(k.into(), self.pairs.len());
+                self.pairs.push((k, v));
+            }
+        }
+    }
+
+    pub fn remove(&mut self, k: Obj) -> Option<Obj> {
+        let i = self.idx.remove(&k.into())?;
+        let (_, v) = self.pairs.remove(i);
+        /* everything after it moved down one */
+        for (k, _) in &self.pairs[i..] {
+            if let Some(j) = self.idx.get_mut(&(*k).into()) {
+                *j -= 1;
+            }
+        }
+        Some(v)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = Obj> + '_ {
+        self.pairs.iter().map(|x| x.0)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = Obj> + '_ {
+        self.pairs.iter().map(|x| x.1)
+    }
+}
+
+/** a later pair replaces an earlier one with the same key */
+impl FromIterator<(Obj, Obj)> for Dict {
+    fn from_iter<I: IntoIterator<Item = (Obj, Obj)>>(it: I) -> Self {
+        let mut d = Dict::default();
+        it.into_iter().for_each(|(k, v)| d.insert(k, v));
+        d
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Table(pub Dict, pub Vec<Obj>);
+
+/** block .0 with some of its args filled in. None is a hole */
 #[derive(Clone, Debug, PartialEq)]
-pub struct Table(pub Vec<(Obj, Obj)>, pub Vec<Obj>);
+pub struct Proj(pub usize, pub Vec<Option<Obj>>);
 
 impl Table {
+    /** how many pairs and items the table holds */
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.0.len() + self.1.len()
+    }
+
+    /** whether i indexes this table by key rather than by position */
+    #[inline]
+    pub fn keyed(&self, i: Obj) -> bool {
+        !self.0.is_empty() || !matches!(i, Obj::U(_))
+    }
+
+    /** the value under key k. symbols are keyed by id, so this never
+     * compares strings */
+    #[inline]
+    pub fn get(&self, k: Obj) -> Option<Obj> {
+        self.0.get(k)
+    }
+
     #[inline]
     pub fn vec(&self) -> &[Obj] {
         &self.1
@@ -69,36 +338,221 @@ impl Table {
     }
 }
 
+/** a body being run: the block it was entered through, its next
+ * instruction, and where its caller was */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+    pub blk: usize,
+    pub body: usize,
+    pub pc: usize,
+    pub caller: Loc,
+}
+
+/** how a run of the vm ended */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Run {
+    /** the script yielded this, and the vm can be resumed */
+    Yielded(Obj),
+    Finished(Obj),
+}
+
+impl Run {
+    /** what was yielded or returned */
+    pub fn value(&self) -> Obj {
+        match self {
+            Run::Yielded(x) | Run::Finished(x) => *x,
+        }
+    }
+}
+
+/** where a coroutine is in its life */
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Status {
+    /** created, and not resumed yet */
+    Ready,
+    Running,
+    /** yielded, and waiting to be resumed */
+    Suspended,
+    /** returned or failed. it can't be resumed again */
+    Dead,
+}
+
+impl Status {
+    pub const ALL: [Status; 4] =
+        [Status::Ready, Status::Running, Status::Suspended, Status::Dead];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Ready => "ready",
+            Status::Running => "running",
+            Status::Suspended => "suspended",
+            Status::Dead => "dead",
+        }
+    }
+}
+
+/** what a body needs to run: the stack it works on, its frames, how
+ * many builtins under it are calling back into bytecode, and the values
+ * its functions' locals have while something else runs */
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Ctx {
+    stack: Vec<Obj>,
+    frames: Vec<Frame>,
+    natives: usize,
+    vars: HashMap<usize, Obj>,
+}
+
+/** function f, run a piece at a time with a stack and frames of its
+ * own, which are kept in ctx while it isn't running */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coro {
+    pub f: Obj,
+    pub status: Status,
+    ctx: Ctx,
+}
+
+/** what entering a block or calling something comes to */
+enum Next {
+    /** a block or body to run */
+    Run(usize),
+    /** nothing to run. this is the value */
+    Value(Obj),
+}
+
 pub trait Machine {
-    fn get_table(&self, x: usize) -> &Table;
+    fn get_table(&self, x: usize) -> Res<&Table>;
     fn get_tables(&self) -> &HashMap<usize, Table>;
 
     fn add_table(&mut self, t: Table) -> usize;
     fn rm_table(&mut self, x: &usize);
 
     /** push obj x to vec v */
-    fn vec_push(&mut self, v: &usize, x: Obj);
+    fn vec_push(&mut self, v: &usize, x: Obj) -> Res<()>;
 
     /** push the last item in vec v */
-    fn vec_last(&mut self, v: &usize);
+    fn vec_last(&mut self, v: &usize) -> Res<()>;
 
     /** pop an item off vec v */
-    fn vec_pop(&mut self, v: &usize);
+    fn vec_pop(&mut self, v: &usize) -> Res<()>;
 }
 
-#[derive(Debug, Clone, PartialEq)]
-pub struct VM<'a> {
-    code: &'a [Instr],
-    blocks: &'a [Blk],
-    bodies: &'a [Body<'a>],
+pub struct VM {
+    prog: Program,
     stack: Vec<Obj>,
     vars: HashMap<usize, Obj>,
     tables: (usize, HashMap<usize, Table>),
+    projs: (usize, HashMap<usize, Proj>),
+    coros: (usize, HashMap<usize, Coro>),
+    /** the running coroutines, innermost last, each with the context
+     * of whatever resumed it */
+    resumers: Vec<(usize, Ctx)>,
+    /** the instruction and block currently executing */
+    at: Loc,
+    /** the bodies being run, innermost last */
+    frames: Vec<Frame>,
+    /** how many builtins are calling back into bytecode. a script can
+     * only yield when none are */
+    natives: usize,
+    /** how many runs are nested on the rust stack. unlike natives, a
+     * coroutine doesn't get its own count, since it shares the stack */
+    runs: usize,
+    /** what the instructions being run hold in rust, where the gc
+     * can't see it, should they call back into bytecode: their operands,
+     * the tables they make and what their calls give back */
+    pins: Vec<Obj>,
+    /** whether an instruction is running, so tables made now get pinned */
+    pinning: bool,
+    /** set by a yield for the run loop to hand back */
+    yielded: Option<Obj>,
+    limits: Limits,
+    /** instructions run since the limits were set */
+    ran: usize,
+    /** pairs and items in all the live tables */
+    elems: usize,
+    dbg: Option<Dbg>,
+    tracer: Option<Box<dyn Tracer>>,
+    /** set once an error has been handed to the tracer, so the frames
+     * it unwinds through don't report it again */
+    unwinding: bool,
+}
+
+/* the debugger and tracer are the host's, so they're left out: a clone
+ * runs on from the same state without them, and two vms are equal when
+ * their states are */
+impl std::fmt::Debug for VM {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("VM")
+            .field("prog", &self.prog)
+            .field("stack", &self.stack)
+            .field("vars", &self.vars)
+            .field("tables", &self.tables)
+            .field("projs", &self.projs)
+            .field("coros", &self.coros)
+            .field("resumers", &self.resumers)
+            .field("at", &self.at)
+            .field("frames", &self.frames)
+            .field("natives", &self.natives)
+            .field("runs", &self.runs)
+            .field("pins", &self.pins)
+            .field("pinning", &self.pinning)
+            .field("yielded", &self.yielded)
+            .field("limits", &self.limits)
+            .field("ran", &self.ran)
+            .field("elems", &self.elems)
+            .field("unwinding", &self.unwinding)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Clone for VM {
+    fn clone(&self) -> Self {
+        Self {
+            prog: self.prog.clone(),
+            stack: self.stack.clone(),
+            vars: self.vars.clone(),
+            tables: self.tables.clone(),
+            projs: self.projs.clone(),
+            coros: self.coros.clone(),
+            resumers: self.resumers.clone(),
+            at: self.at,
+            frames: self.frames.clone(),
+            natives: self.natives,
+            runs: self.runs,
+            pins: self.pins.clone(),
+            pinning: self.pinning,
+            yielded: self.yielded,
+            limits: self.limits.clone(),
+            ran: self.ran,
+            elems: self.elems,
+            dbg: None,
+            tracer: None,
+            unwinding: self.unwinding,
+        }
+    }
+}
+
+impl PartialEq for VM {
+    fn eq(&self, x: &Self) -> bool {
+        self.prog == x.prog
+            && self.stack == x.stack
+            && self.vars == x.vars
+            && self.tables == x.tables
+            && self.projs == x.projs
+            && self.coros == x.coros
+            && self.resumers == x.resumers
+            && self.at == x.at
+            && self.frames == x.frames
+            && self.natives == x.natives
+            && self.yielded == x.yielded
+    }
 }
 
-impl<'a> Machine for VM<'a> {
-    fn get_table(&self, x: usize) -> &Table {
-        &self.tables.1[&x]
+impl Machine for VM {
+    fn get_table(&self, x: usize) -> Res<&Table> {
+        match self.tables.1.get(&x) {
+            Some(t) => Ok(t),
+            None => err!(self, NoTable { table: x }),
+        }
     }
 
     #[inline]
@@ -108,241 +562,656 @@ impl<'a> Machine for VM<'a> {
 
     fn add_table(&mut self, x: Table) -> usize {
         let n = self.tables.0;
+        if self.pinning {
+            self.pins.push(Obj::T(n));
+        }
+        self.elems += x.size();
         self.tables.1.insert(n, x);
         self.tables.0 += 1;
+        trace!(self, table(n));
         n
     }
 
     #[inline]
     fn rm_table(&mut self, x: &usize) {
-        self.tables.1.remove(x);
+        if let Some(t) = self.tables.1.remove(x) {
+            self.elems -= t.size();
+        }
     }
 
-    fn vec_push(&mut self, v: &usize, x: Obj) {
-        if let Some(Obj::T(t)) = self.vars.get_mut(&v) {
-            self.tables.1
-                .get_mut(t)
-                .expect(&format!(
-                    "table {t} not found (referenced by variable {v}"
-                ))
-                .vec_push(x);
-        } else {
-            unreachable!()
-        }
+    fn vec_push(&mut self, v: &usize, x: Obj) -> Res<()> {
+        let t = self.var_table(v)?;
+        self.acyclic(t, x)?;
+        self.edit_table(t, |t| t.vec_push(x))
     }
 
-    fn vec_last(&mut self, v: &usize) {
-        if let Obj::T(t) = self.vars[v] {
-            let v = self.get_table(t).vec();
-            self.stack.push(v[v.len() - 1]);
-        } else {
-            unreachable!()
-        }
+    fn vec_last(&mut self, v: &usize) -> Res<()> {
+        let t = self.var_table(v)?;
+        let Some(x) = self.get_table(t)?.vec().last().copied() else {
+            return err!(self, Underflow);
+        };
+        self.stack.push(x);
+        Ok(())
     }
 
-    fn vec_pop(&mut self, v: &usize) {
-        if let Some(Obj::T(t)) = self.vars.get_mut(&v) {
-            self.tables.1
-                .get_mut(t)
-                .expect(&format!(
-                    "table {t} not found (referenced by variable {v}"
-                ))
-                .vec_pop();
-        } else {
-            unreachable!()
+    fn vec_pop(&mut self, v: &usize) -> Res<()> {
+        let t = self.var_table(v)?;
+        if self.get_table(t)?.vec().is_empty() {
+            return err!(self, Underflow);
         }
+        self.edit_table(t, |t| t.vec_pop())
     }
 }
 
-impl<'a> VM<'a> {
-    pub fn new(
-        code: &'a [Instr],
-        blocks: &'a [Blk],
-        bodies: &'a [Body<'a>],
-    ) -> Self {
+impl VM {
+    pub fn new(prog: Program) -> Self {
         Self {
-            code,
-            blocks,
-            bodies,
+            prog,
             stack: Vec::new(),
             vars: HashMap::new(),
             tables: (0, HashMap::new()),
+            projs: (0, HashMap::new()),
+            coros: (0, HashMap::new()),
+            resumers: Vec::new(),
+            at: Loc::default(),
+            frames: Vec::new(),
+            natives: 0,
+            runs: 0,
+            pins: Vec::new(),
+            pinning: false,
+            yielded: None,
+            limits: Limits::default(),
+            ran: 0,
+            elems: 0,
+            dbg: None,
+            tracer: None,
+            unwinding: false,
+        }
+    }
+
+    #[inline]
+    pub fn loc(&self) -> Loc {
+        self.at
+    }
+
+    /** how many bodies deep we are */
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    #[inline]
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    #[inline]
+    pub fn code(&self) -> &[Instr] {
+        &self.prog.instrs
+    }
+
+    #[inline]
+    pub fn blocks(&self) -> &[Blk] {
+        &self.prog.blocks
+    }
+
+    #[inline]
+    pub fn bodies(&self) -> &[Body] {
+        &self.prog.bodies
+    }
+
+    #[inline]
+    pub fn program(&self) -> &Program {
+        &self.prog
+    }
+
+    /** run p from now on. p has to be the program the vm has, with
+     * any more code compiled onto the end, so that what's running and
+     * everything that refers to a block stays where it was */
+    pub fn load(&mut self, p: Program) -> Res<()> {
+        if !p.extends(&self.prog) {
+            return err!(self, Reload);
+        }
+        self.prog = p;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn stack(&self) -> &[Obj] {
+        &self.stack
+    }
+
+    #[inline]
+    pub fn vars(&self) -> &HashMap<usize, Obj> {
+        &self.vars
+    }
+
+    /** attach a debugger. it's consulted before every instruction */
+    pub fn debug(&mut self, d: impl Debugger + 'static) {
+        self.dbg = Some(Dbg::new(Box::new(d)));
+    }
+
+    /** detach the debugger, dropping its breakpoints */
+    pub fn undebug(&mut self) {
+        self.dbg = None;
+    }
+
+    pub fn break_at(&mut self, b: Break) {
+        if let Some(d) = &mut self.dbg {
+            d.breaks.push(b);
+        }
+    }
+
+    /** limit what execution may use from here on. fuel counts from
+     * when the limits are set */
+    pub fn limit(&mut self, l: Limits) {
+        self.limits = l;
+        self.ran = 0;
+    }
+
+    /** how many instructions have run since the limits were set */
+    #[inline]
+    pub fn ran(&self) -> usize {
+        self.ran
+    }
+
+    /** install a tracer, replacing any previous one */
+    pub fn trace(&mut self, t: impl Tracer + 'static) {
+        self.tracer = Some(Box::new(t));
+    }
+
+    pub fn untrace(&mut self) {
+        self.tracer = None;
+    }
+
+    /** set how the debugger resumes, e.g. Step::Into to stop at the
+     * first instruction */
+    pub fn step(&mut self, s: Step) {
+        if let Some(d) = &mut self.dbg {
+            d.step = s;
+            d.depth = self.frames.len();
+        }
+    }
+
+    /** hand control to the debugger if it wants to stop here */
+    fn dbg_stop(&mut self, x: Instr) -> Res<()> {
+        let Some(mut d) = self.dbg.take() else {
+            return Ok(());
+        };
+        let start = self.bodies().get(self.at.body).map_or(0, |b| b.start);
+
+        let mut r = Ok(());
+        let why = match x {
+            Instr::Break => Some(Why::Break),
+            _ => d.why(self.at, start, self.frames.len()),
+        };
+        if let Some(why) = why {
+            let s = Stop {
+                at: self.at,
+                instr: x,
+                depth: self.frames.len(),
+                why,
+            };
+            match d.ui.stop(self, &s, &mut d.breaks) {
+                Step::Quit => r = err!(self, Halted),
+                step => {
+                    d.step = step;
+                    d.depth = self.frames.len();
+                }
+            }
         }
+
+        self.dbg = Some(d);
+        r
     }
 
     pub fn fmt(&self, x: &Obj) -> String {
+        self.show(x, 0)
+    }
+
+    /** fmt, d tables deep. what's past the depth limit is left out */
+    fn show(&self, x: &Obj, d: usize) -> String {
         use Obj::*;
+        let show = |x: &Obj| self.show(x, d + 1);
         match x {
-            x @ (C(_) | F(_) | U(_) | Fun(_)) => format!("{x}"),
-            T(x) => {
-                let t = self.get_table(*x);
+            S(i) => format!("`{}", self.prog.syms.name(*i)),
+            x @ (C(_) | F(_) | U(_) | Fun(_) | N | Co(_)) => format!("{x}"),
+            _ if d >= self.limits.depth => "...".to_string(),
+            P(i) => {
+                let Ok(Proj(f, v)) = self.get_proj(*i) else {
+                    return format!("{x}");
+                };
+                let v = v
+                    .iter()
+                    .map(|x| x.map_or(String::new(), |x| show(&x)))
+                    .collect::<Vec<_>>();
+                format!("{}[{}]", Fun(*f), v.join(";"))
+            }
+            T(_) if let Some(s) = self.string(*x) => format!("{s:?}"),
+            T(i) => {
+                let Ok(t) = self.get_table(*i) else {
+                    return format!("{x}");
+                };
                 format!(
                     "[{}|{}]",
-                    t.0.iter()
-                        .map(|(x, y)| format!(
-                            "{}: {}",
-                            self.fmt(x),
-                            self.fmt(y)
-                        ))
+                    t.0.pairs()
+                        .iter()
+                        .map(|(x, y)| format!("{}: {}", show(x), show(y)))
                         .collect::<Vec<_>>()
                         .join(", "),
-                    t.1.iter()
-                        .map(|x| self.fmt(x))
-                        .collect::<Vec<_>>()
+                    t.1.iter().map(show).collect::<Vec<_>>()
                         .join(", ")
                 )
             }
         }
     }
 
-    fn iter_body(&self, i: usize) -> BodyIterator<'a> {
-        BodyIterator::from(&self.code, &self.bodies[i])
+    #[inline]
+    fn pop(&mut self) -> Res<Obj> {
+        match self.stack.pop() {
+            Some(x) => Ok(x),
+            None => err!(self, Underflow),
+        }
     }
 
-    #[cfg(test)]
+    #[inline]
     fn load_var(&self, i: &usize) -> Res<Obj> {
-        self.vars
-            .get(&i)
-            .map(|x| Ok(*x))
-            .unwrap_or(err_fmt!("no var {i}"))
+        match self.vars.get(i) {
+            Some(x) => Ok(*x),
+            None => err!(self, NoVar { var: *i }),
+        }
     }
 
-    #[cfg(not(test))]
-    #[inline]
-    fn load_var(&self, i: &usize) -> Res<Obj> {
-        Ok(self.vars[i])
+    /** the table held by variable v */
+    fn var_table(&self, v: &usize) -> Res<usize> {
+        match self.load_var(v)? {
+            Obj::T(t) => Ok(t),
+            x => err!(self, Type {
+                expected: ObjType::T,
+                actual: x.ty()
+            }),
+        }
+    }
+
+    /** change table x with f, keeping count of what the tables hold */
+    fn edit_table<R>(
+        &mut self,
+        x: usize,
+        f: impl FnOnce(&mut Table) -> R,
+    ) -> Res<R> {
+        let at = self.loc();
+        let Some(t) = self.tables.1.get_mut(&x) else {
+            return Err(Error::NoTable { table: x, at });
+        };
+        let n = t.size();
+        let r = f(t);
+        self.elems = self.elems + t.size() - n;
+        Ok(r)
     }
 
     #[inline]
     fn find_label(&self, x: usize) -> Option<usize> {
-        self.code
-            .iter()
-            .enumerate()
-            .find(|(_, i)| i == &&Instr::Label(x))
-            .map(|(i, x)| {
-                if let Instr::Label(_) = x {
-                    i
-                } else {
-                    unreachable!()
-                }
-            })
+        self.prog.instrs.iter().position(|i| i == &Instr::Label(x))
     }
 
     fn exe_instr(&mut self, x: &Instr) -> Res<()> {
-        #[cfg(test)]
-        let len = self
-            .code
-            .iter()
-            .map(|x| format!("{x:?}").len())
-            .fold(0, |x, y| x.max(y))
-            + 2;
-        dbgln!(
-            "instr: {} {:?}",
-            format!("{x:?}")
-                .pad_to_width_with_alignment(len, Alignment::Right)
-                .purple(),
-            self.stack,
-        );
-
-        let r = match x {
+        match x {
             Instr::Push(x) => self.stack.push(*x),
             Instr::Pop => {
-                let _ = self.stack.pop();
-                ()
+                self.pop()?;
             }
 
-            Instr::Local(x) => self
-                .stack
-                .pop()
-                .map(|o| self.vars.insert(*x, o))
-                .and_then(|_| Some(()))
-                .expect("expected variable on stack for local() call"),
+            Instr::Local(x) => {
+                let o = self.pop()?;
+                self.vars.insert(*x, o);
+            }
 
             Instr::Load(x) => self.stack.push(self.load_var(x)?),
 
             Instr::AddF => {
-                impl_math!(self, Obj::F => (Obj::F, Obj::F) {|x, y|x+y})
+                impl_math!(self, {|x, y| x.checked_add(y)}, {|x, y| x + y})
             }
             Instr::SubF => {
-                impl_math!(self, Obj::F => (Obj::F, Obj::F) {|x, y|x-y})
+                impl_math!(self, {|x, y| x.checked_sub(y)}, {|x, y| x - y})
             }
             Instr::MulF => {
-                impl_math!(self, Obj::F => (Obj::F, Obj::F) {|x, y|x*y})
+                impl_math!(self, {|x, y| x.checked_mul(y)}, {|x, y| x * y})
+            }
+            Instr::DivF => impl_math!(self, {|_, _| None}, {|x, y| x / y}),
+            Instr::DivU => {
+                impl_math!(self, {|x, y| x.checked_div(y)}, {|x, y| {
+                    (x / y).floor()
+                }})
             }
-            Instr::DivF => {
-                impl_math!(self, Obj::F => (Obj::F, Obj::F) {|x, y|x/y})
+            Instr::ModU => {
+                impl_math!(self, {|x, y| x.checked_rem(y)}, {|x, y| {
+                    x.rem_euclid(y)
+                }})
             }
 
-            Instr::CmpF => {
-                let (y, x) = pop_assign!(self.stack => (
-                        Obj::F(x) => x,
-                        Obj::F(x) => x,
-                ));
-                self.stack.push(Obj::F(if x < y {
+            Instr::CmpF => impl_math!(self, {|_, _| None}, {|x, y| {
+                if x < y {
                     -1.
                 } else if x > y {
                     1.
                 } else {
                     0.
-                }));
-            }
+                }
+            }}),
 
             Instr::NegF => {
-                let x = pop_assign!(self.stack => (Obj::F(x) => x));
-                self.stack.push(Obj::F(-x));
+                let x = self.pop()?;
+                /* from 0, so there's no -0 */
+                let f = |x| num(x).then(|| Obj::F(0. - flt(x)));
+                let r = self.atomic1(x, ObjType::F, &f, 0)?;
+                self.stack.push(r);
             }
 
-            Instr::Apply0 => {
-                let x = pop_assign!(self.stack => (
-                    Obj::Fun(x) => x,
-                ));
-                let r = self.exe_block(x)?;
+            Instr::Lt => {
+                let (y, x) = pop_assign!(self => (_, _));
+                let p = self.prog.clone();
+                let f = |x, y| lt(x, y, &p.syms).map(bool);
+                let r = self.atomic2(x, y, &f, 0)?;
                 self.stack.push(r);
             }
 
-            Instr::Apply1 => {
-                let (y, x) = pop_assign!(self.stack => (
-                    y => y,
-                    Obj::Fun(x) => x,
-                ));
+            Instr::Gt => {
+                let (y, x) = pop_assign!(self => (_, _));
+                let p = self.prog.clone();
+                let f = |x, y| lt(y, x, &p.syms).map(bool);
+                let r = self.atomic2(x, y, &f, 0)?;
+                self.stack.push(r);
+            }
 
-                self.stack.push(y);
-                let r = self.exe_block(x)?;
+            Instr::Eq => {
+                let (y, x) = pop_assign!(self => (_, _));
+                let r = self.atomic2(x, y, &|x, y| Some(bool(eq(x, y))), 0)?;
                 self.stack.push(r);
             }
 
-            Instr::ApplyN => {
-                let f = pop_assign!(self.stack => (Obj::Fun(x) => x));
-                let v = pop_assign!(self.stack, 0..self.stack.len());
+            Instr::Match => {
+                let (y, x) = pop_assign!(self => (_, _));
+                let r = bool(self.matches(x, y, 0)?);
+                self.stack.push(r);
+            }
 
-                v.iter().for_each(|x| self.stack.push(*x));
-                let r = self.exe_block(f)?;
+            Instr::And => {
+                let (y, x) = pop_assign!(self => (_, _));
+                let r = self.atomic2(x, y, &|x, y| {
+                    let (x, y) = (truth(x)?, truth(y)?);
+                    Some(bool(x && y))
+                }, 0)?;
                 self.stack.push(r);
             }
 
-            Instr::Table(x) => {
-                let mut r = Vec::new();
-                /* first iter the  arguments and put them in a vec */
-                let mut i = pop_assign!(self.stack, 0..2 * *x).into_iter();
+            Instr::Or => {
+                let (y, x) = pop_assign!(self => (_, _));
+                let r = self.atomic2(x, y, &|x, y| {
+                    let (x, y) = (truth(x)?, truth(y)?);
+                    Some(bool(x || y))
+                }, 0)?;
+                self.stack.push(r);
+            }
 
-                while let Some(a) = i.next() {
-                    if let Some(b) = i.next() {
-                        r.push((a, b));
-                    } else {
-                        err_fmt!("non-rectangular stack in Table().")?
-                    }
-                }
+            Instr::Not => {
+                let x = self.pop()?;
+                let f = |x| truth(x).map(|x| bool(!x));
+                let r = self.atomic1(x, ObjType::F, &f, 0)?;
+                self.stack.push(r);
+            }
 
-                let t = self.add_table(Table(r, Vec::new()));
-                self.stack.push(Obj::T(t));
+            Instr::Apply0 => {
+                let f = self.pop()?;
+                self.apply(f, Vec::new())?;
             }
 
-            Instr::PopVec => {
-                let i = pop_assign!(self.stack => (Obj::T(x) => x));
-                let v = self
-                    .get_table(i)
-                    .vec()
-                    .iter()
+            Instr::Apply1 => {
+                let (y, f) = pop_assign!(self => (_, _));
+                self.apply(f, vec![Some(y)])?;
+            }
+
+            Instr::Apply2 => {
+                let (z, y, f) = pop_assign!(self => (_, _, _));
+                self.apply(f, vec![Some(y), Some(z)])?;
+            }
+
+            Instr::ApplyN => {
+                let (f, x) = pop_assign!(self => (_, _));
+                let v = self.list(x)?;
+                self.apply(f, v.into_iter().map(Some).collect())?;
+            }
+
+            Instr::Cat => {
+                let (y, x) = pop_assign!(self => (_, _));
+                let mut v = self.list(x)?;
+                v.append(&mut self.list(y)?);
+                let r = self.mk_vec(v);
+                self.stack.push(r);
+            }
+
+            Instr::Len => {
+                let x = self.pop()?;
+                let n = self.list(x)?.len();
+                self.stack.push(Obj::U(n));
+            }
+
+            Instr::Take => {
+                let (x, n) = pop_assign!(self => (_, U));
+                let mut v = self.list(x)?;
+                v.truncate(n);
+                let r = self.mk_vec(v);
+                self.stack.push(r);
+            }
+
+            Instr::Drop => {
+                let (x, n) = pop_assign!(self => (_, U));
+                let v = self.list(x)?;
+                let r = self.mk_vec(v[n.min(v.len())..].to_vec());
+                self.stack.push(r);
+            }
+
+            Instr::Split => {
+                let (x, sep) = pop_assign!(self => (_, _));
+                let (sep, x) = (self.text(sep)?, self.text(x)?);
+                let v = if sep.is_empty() {
+                    vec![x.as_str()]
+                } else {
+                    x.split(sep.as_str()).collect()
+                };
+                let v = v.into_iter().map(|x| self.mk_str(x)).collect();
+                let r = self.mk_vec(v);
+                self.stack.push(r);
+            }
+
+            Instr::Join => {
+                let (x, sep) = pop_assign!(self => (_, _));
+                let sep = self.text(sep)?;
+                let v = self
+                    .items(x)?
+                    .into_iter()
+                    .map(|x| self.text(x))
+                    .collect::<Res<Vec<_>>>()?;
+                let r = self.mk_str(&v.join(&sep));
+                self.stack.push(r);
+            }
+
+            Instr::Upper => {
+                let x = self.pop()?;
+                let f = |x| match x {
+                    Obj::C(c) => c.to_uppercase().next().map(Obj::C),
+                    _ => None,
+                };
+                let r = self.atomic1(x, ObjType::C, &f, 0)?;
+                self.stack.push(r);
+            }
+
+            Instr::Lower => {
+                let x = self.pop()?;
+                let f = |x| match x {
+                    Obj::C(c) => c.to_lowercase().next().map(Obj::C),
+                    _ => None,
+                };
+                let r = self.atomic1(x, ObjType::C, &f, 0)?;
+                self.stack.push(r);
+            }
+
+            Instr::Str => {
+                let x = self.pop()?;
+                let r = self.str(x, 0)?;
+                self.stack.push(r);
+            }
+
+            Instr::Parse => {
+                let x = self.pop()?;
+                let r = self.parse(x, 0)?;
+                self.stack.push(r);
+            }
+
+            Instr::Table(x) => {
+                let mut r = Dict::default();
+                /* keys and values were pushed in pairs, key first */
+                let mut i = pop_assign!(self, x.saturating_mul(2))
+                    .into_iter()
+                    .rev();
+
+                while let Some(k) = i.next() {
+                    if let Some(v) = i.next() {
+                        r.insert(k, v);
+                    } else {
+                        err!(self, Underflow)?
+                    }
+                }
+
+                let t = self.add_table(Table(r, Vec::new()));
+                self.stack.push(Obj::T(t));
+            }
+
+            Instr::Yield => {
+                if self.natives > 0 {
+                    return err!(self, Yield);
+                }
+                let x = self.pop()?;
+                match self.resumers.is_empty() {
+                    true => self.yielded = Some(x),
+                    false => self.suspend(x),
+                }
+            }
+
+            Instr::Coro => {
+                let f = match self.pop()? {
+                    f @ (Obj::Fun(_) | Obj::P(_)) => f,
+                    x => err!(self, Type {
+                        expected: ObjType::Fun,
+                        actual: x.ty()
+                    })?,
+                };
+                let c = self.add_coro(Coro {
+                    f,
+                    status: Status::Ready,
+                    ctx: Ctx::default(),
+                });
+                self.stack.push(Obj::Co(c));
+            }
+
+            Instr::Resume => {
+                let (x, c) = pop_assign!(self => (_, Co));
+                self.resume_coro(c, x)?;
+            }
+
+            Instr::Status => {
+                let c = pop_assign!(self => (Co));
+                let s = self.status(c)?;
+                let s = self.prog.syms.get(s.name());
+                self.stack.push(s.map_or(Obj::N, Obj::S));
+            }
+
+            Instr::Signal => {
+                let x = self.pop()?;
+                err!(self, Signal { value: x })?
+            }
+
+            Instr::Assign => {
+                let (v, i, x) = pop_assign!(self => (_, _, T));
+                /* x[1 2]:(10 20) sets each index to its own value */
+                let vs = match v {
+                    Obj::T(_) if !self.is_key(x, i)? => {
+                        let (a, b) = (self.items(i)?, self.items(v)?);
+                        if a.len() != b.len() {
+                            err!(self, Length {
+                                expected: a.len(),
+                                actual: b.len()
+                            })?
+                        }
+                        Some(b)
+                    }
+                    _ => None,
+                };
+                self.amend(x, i, &|_, _, _, n| {
+                    Ok(vs.as_ref().map_or(v, |vs| vs[n]))
+                })?;
+                self.stack.push(Obj::T(x));
+            }
+
+            Instr::Dict => {
+                let (v, k) = pop_assign!(self => (_, _));
+                let (k, v) = (self.list(k)?, self.list(v)?);
+                if k.len() != v.len() {
+                    err!(self, Length {
+                        expected: k.len(),
+                        actual: v.len()
+                    })?
+                }
+                let d = k.into_iter().zip(v).collect();
+                let t = self.add_table(Table(d, Vec::new()));
+                self.stack.push(Obj::T(t));
+            }
+
+            Instr::Get => {
+                let (or, k, d) = pop_assign!(self => (_, _, T));
+                let r = self.get_table(d)?.get(k).unwrap_or(or);
+                self.stack.push(r);
+            }
+
+            Instr::Put => {
+                let (v, k, d) = pop_assign!(self => (_, _, T));
+                self.acyclic(d, k)?;
+                self.acyclic(d, v)?;
+                self.edit_table(d, |t| t.0.insert(k, v))?;
+                self.stack.push(Obj::T(d));
+            }
+
+            Instr::Del => {
+                let (k, d) = pop_assign!(self => (_, T));
+                self.edit_table(d, |t| t.0.remove(k))?;
+                self.stack.push(Obj::T(d));
+            }
+
+            Instr::Has => {
+                let (k, d) = pop_assign!(self => (_, T));
+                let r = self.get_table(d)?.get(k).is_some();
+                self.stack.push(bool(r));
+            }
+
+            Instr::Keys => {
+                let d = pop_assign!(self => (T));
+                let v = self.get_table(d)?.0.keys().collect();
+                let r = self.mk_vec(v);
+                self.stack.push(r);
+            }
+
+            Instr::Values => {
+                let d = pop_assign!(self => (T));
+                let v = self.get_table(d)?.0.values().collect();
+                let r = self.mk_vec(v);
+                self.stack.push(r);
+            }
+
+            Instr::PopVec => {
+                let i = pop_assign!(self => (T));
+                let v = self
+                    .get_table(i)?
+                    .vec()
+                    .iter()
                     .rev()
                     .copied()
                     .collect::<Vec<Obj>>();
@@ -352,262 +1221,3109 @@ impl<'a> VM<'a> {
             }
 
             Instr::Vec(x) => {
-                let v = pop_assign!(self.stack, 0..*x)
+                let v = pop_assign!(self, *x)
                     .into_iter()
                     .rev()
                     .collect::<Vec<_>>();
-                let u = self.add_table(Table(Vec::new(), v));
+                let u = self.add_table(Table(Dict::default(), v));
 
                 self.stack.push(Obj::T(u));
             }
 
             Instr::VecFull => {
-                let v = pop_assign!(self.stack, 0..self.stack.len())
+                let v = pop_assign!(self, self.stack.len())
                     .into_iter()
                     .rev()
                     .collect::<Vec<_>>();
-                let t = self.add_table(Table(Vec::new(), v));
+                let t = self.add_table(Table(Dict::default(), v));
 
                 self.stack.push(Obj::T(t));
             }
 
             Instr::VecPush(v) => {
-                let x = pop_assign!(self.stack => (x => x));
-                self.vec_push(v, x);
+                let x = pop_assign!(self => (_));
+                self.vec_push(v, x)?;
             }
 
-            Instr::VecLast(v) => {
-                self.vec_last(v);
-            }
+            Instr::VecLast(v) => self.vec_last(v)?,
+
+            Instr::VecPop(v) => self.vec_pop(v)?,
 
             Instr::Dup => {
-                self.stack.push(
-                    *self.stack.iter().last().expect("item on stack for dup"),
-                );
+                let x = pop_assign!(self => (_));
+                self.stack.push(x);
+                self.stack.push(x);
             }
 
             Instr::Swap2 => {
-                let (y, x) = pop_assign!(self.stack => (
-                    x => x,
-                    x => x,
-                ));
+                let (y, x) = pop_assign!(self => (_, _));
 
                 self.stack.push(y);
                 self.stack.push(x);
             }
 
-            Instr::Jmp(i) => {
-                let r = self.exe_block(*i)?;
-                self.stack.push(r);
-            }
-
-            Instr::LJmpNZ(x) => {
-                let n = pop_assign!(self.stack => (
-                        Obj::F(x) => x,
-                ));
-                if n != 0. {
-                    let i = self.find_label(*x).expect("label not found");
+            Instr::LJmpZ(l) | Instr::LJmpNZ(l) => {
+                if self.zero()? == matches!(x, Instr::LJmpZ(_)) {
+                    let Some(i) = self.find_label(*l) else {
+                        return err!(self, NoLabel { label: *l });
+                    };
                     dbgln!(
                         alert,
-                        "found label {x}. jumping to instruction {i}"
+                        "found label {l}. jumping to instruction {i}"
                     );
-                    self.exe_at(i)?;
+                    if let Some(f) = self.frames.last_mut() {
+                        f.pc = i;
+                    }
                 }
             }
 
-            Instr::Label(_) => (),
+            /* the debugger has already stopped on a Break */
+            Instr::Label(_) | Instr::Nop | Instr::Break => (),
 
             #[allow(unreachable_patterns)]
-            x => return err_fmt!("invalid instruction: {x:?}"),
+            x => return err!(self, Invalid { instr: *x }),
         };
 
-        Ok(r)
+        Ok(())
     }
 
-    pub fn exe_body(&mut self, i: usize) -> Res<Obj> {
-        for x in self.iter_body(i) {
-            self.exe_instr(x)?;
-        }
+    /** run x, handing it to exe_instr unless it can call back into
+     * bytecode. a nested run holds the frame of what called back on the
+     * rust stack, so those are kept out of exe_instr's, which is huge */
+    fn exe_call(&mut self, x: &Instr) -> Res<()> {
+        match x {
+            Instr::Proj(n, holes) => {
+                let (n, holes) = (*n, *holes);
+                /* the mask only has room for so many args */
+                if n > usize::BITS as usize {
+                    return err!(self, Invalid { instr: *x });
+                }
+                let arg = |i: usize| {
+                    u32::try_from(i)
+                        .ok()
+                        .and_then(|i| 1usize.checked_shl(i))
+                        .is_some_and(|b| holes & b == 0)
+                };
+                let k = (0..n).filter(|i| arg(*i)).count();
+                let mut v = pop_assign![self, k];
+                let f = self.pop()?;
+                let args = (0..n)
+                    .map(|i| arg(i).then(|| v.pop()))
+                    .map(Option::flatten)
+                    .collect();
+                let r = self.call(f, args)?;
+                self.stack.push(r);
+            }
 
-        match self.stack.pop() {
-            Some(x) => Ok(x),
-            None => err_fmt!("invalid return: no value on top of stack"),
+            Instr::Each => {
+                let (x, f) = pop_assign!(self => (_, _));
+                let r = self
+                    .items(x)?
+                    .into_iter()
+                    .map(|x| self.call(f, vec![Some(x)]))
+                    .collect::<Res<Vec<_>>>()?;
+                let r = self.mk_vec(r);
+                self.stack.push(r);
+            }
+
+            Instr::Over => {
+                let (x, f) = pop_assign!(self => (_, _));
+                let mut v = self.items(x)?.into_iter();
+                let r = match v.next() {
+                    Some(a) => v.try_fold(a, |a, y| {
+                        self.call(f, vec![Some(a), Some(y)])
+                    })?,
+                    None => x,
+                };
+                self.stack.push(r);
+            }
+
+            Instr::Scan => {
+                let (x, f) = pop_assign!(self => (_, _));
+                let mut r: Vec<Obj> = Vec::new();
+                for y in self.items(x)? {
+                    let a = match r.last() {
+                        Some(a) => self.call(f, vec![Some(*a), Some(y)])?,
+                        None => y,
+                    };
+                    r.push(a);
+                }
+                let r = self.mk_vec(r);
+                self.stack.push(r);
+            }
+
+            Instr::EachPrior => {
+                let (x, f) = pop_assign!(self => (_, _));
+                let v = self.items(x)?;
+                let mut r = v.first().copied().into_iter().collect::<Vec<_>>();
+                for w in v.windows(2) {
+                    r.push(self.call(f, vec![Some(w[1]), Some(w[0])])?);
+                }
+                let r = self.mk_vec(r);
+                self.stack.push(r);
+            }
+
+            Instr::EachLeft => {
+                let (y, x, f) = pop_assign!(self => (_, _, _));
+                let r = self
+                    .items(x)?
+                    .into_iter()
+                    .map(|x| self.call(f, vec![Some(x), Some(y)]))
+                    .collect::<Res<Vec<_>>>()?;
+                let r = self.mk_vec(r);
+                self.stack.push(r);
+            }
+
+            Instr::EachRight => {
+                let (y, x, f) = pop_assign!(self => (_, _, _));
+                let r = self
+                    .items(y)?
+                    .into_iter()
+                    .map(|y| self.call(f, vec![Some(x), Some(y)]))
+                    .collect::<Res<Vec<_>>>()?;
+                let r = self.mk_vec(r);
+                self.stack.push(r);
+            }
+
+            Instr::Trap => {
+                let (h, args, f) = pop_assign!(self => (_, _, _));
+                let args = self.list(args)?.into_iter().map(Some).collect();
+                /* the call gets a stack of its own, so it can't eat into
+                 * ours and there's nothing to unwind if it fails. ours is
+                 * pinned meanwhile, so gc still sees it */
+                let (stack, at) = (std::mem::take(&mut self.stack), self.at);
+                let p = self.pins.len();
+                self.pins.extend_from_slice(&stack);
+                let r = self.call(f, args);
+                self.pins.truncate(p);
+                (self.stack, self.at) = (stack, at);
+
+                let r = match r {
+                    /* stopping from outside isn't the script's to catch */
+                    Err(e @ Error::Halted { .. }) => Err(e)?,
+                    Err(e) => {
+                        self.unwinding = false;
+                        let x = match e {
+                            Error::Signal { value, .. } => value,
+                            e => self.mk_str(e.kind()),
+                        };
+                        self.call(h, vec![Some(x)])?
+                    }
+                    Ok(x) => x,
+                };
+                self.stack.push(r);
+            }
+
+            Instr::Index => {
+                let (i, x) = pop_assign!(self => (_, _));
+                let r = self.dig(x, &[i], 0)?;
+                self.stack.push(r);
+            }
+
+            Instr::IndexDeep => {
+                let (i, x) = pop_assign!(self => (_, _));
+                let p = self.list(i)?;
+                let r = self.dig(x, &p, 0)?;
+                self.stack.push(r);
+            }
+
+            Instr::Amend => {
+                let (f, i, x) = pop_assign!(self => (_, _, T));
+                let t = self.get_table(x)?.clone();
+                let t = self.add_table(t);
+                self.amend(t, i, &|vm, t, i, _| {
+                    let x = vm.at(t, i)?;
+                    vm.call(f, vec![Some(x)])
+                })?;
+                self.stack.push(Obj::T(t));
+            }
+
+            Instr::Jmp(i) => self.enter(*i, false)?,
+
+            Instr::JmpZ(i) => {
+                if self.zero()? {
+                    /* block i takes the place of the rest of the body */
+                    let code = &self.prog.instrs;
+                    if let Some(f) = self.frames.last_mut() {
+                        f.pc = code[f.pc..]
+                            .iter()
+                            .position(|x| x == &Instr::Ret)
+                            .map_or(code.len(), |n| f.pc + n);
+                    }
+                    self.enter(*i, false)?;
+                }
+            }
+
+            x => return self.exe_instr(x),
+        };
+
+        Ok(())
+    }
+
+    /** pop a number and give whether it's 0, for the branches */
+    fn zero(&mut self) -> Res<bool> {
+        match self.pop()? {
+            n @ (Obj::F(_) | Obj::U(_)) => Ok(flt(n) == 0.),
+            n => err!(self, Type {
+                expected: ObjType::F,
+                actual: n.ty()
+            }),
         }
     }
 
-    fn exe_at(&mut self, mut i: usize) -> Res<()> {
-        while i < self.code.len() {
-            let x = self.code[i];
-            if x == Instr::Ret {
-                break;
+    /** run until the frame above base returns, or the script yields */
+    fn run(&mut self, base: usize) -> Res<Run> {
+        /* frames above base are only ours while no coroutine we resumed
+         * is running */
+        let depth = self.resumers.len();
+        while let Some(f) = self.frames.last_mut() {
+            let (i, blk, body) = (f.pc, f.blk, f.body);
+            f.pc += 1;
+            let x = match self.prog.instrs.get(i) {
+                Some(Instr::Ret) | None => {
+                    let r = self.leave();
+                    if self.resumers.len() == depth && self.frames.len() <= base
+                    {
+                        return Ok(Run::Finished(r));
+                    }
+                    match self.frames.is_empty() {
+                        true => self.finish(r),
+                        false => self.stack.push(r),
+                    }
+                    continue;
+                }
+                Some(x) => *x,
+            };
+
+            self.at = Loc { instr: i, blk, body };
+            if let Err(e) = self.tick(x) {
+                let r = self.raised(Err(e));
+                self.abandon(depth, &r);
+                self.unwind(base, &r);
+                return r;
+            }
+            if let Some(x) = self.yielded.take() {
+                return Ok(Run::Yielded(x));
             }
-            self.exe_instr(&x)?;
-            i += 1;
         }
-        Ok(())
+        err!(self, Underflow)
     }
 
-    pub fn exe_block(&mut self, i: usize) -> Res<Obj> {
-        match self.blocks[i] {
-            Blk(BlkType::Fun, Time::Immediate, i) => {
-                dbgln!(
-                    alert,
-                    "executing immediate body {i}: jumping to {}",
-                    self.bodies[i].start
-                );
-                self.exe_body(i)
-            }
-            Blk(BlkType::Fun, Time::Deferred, _) if self.stack.len() == 0 => {
-                dbgln!(alert, "deferred block with empty stack. returning.");
-                Ok(Obj::Fun(i))
+    /** execute x, as long as the debugger and the limits allow it */
+    fn tick(&mut self, x: Instr) -> Res<()> {
+        if self.dbg.is_some() {
+            self.dbg_stop(x)?;
+        }
+        self.spend()?;
+        trace!(self, instr(&x));
+
+        /* an instruction that pops no more than three operands can
+         * still be holding them when it calls back */
+        let p = self.pins.len();
+        let n = self.stack.len().saturating_sub(3);
+        self.pins.extend_from_slice(&self.stack[n..]);
+        let pinning = std::mem::replace(&mut self.pinning, true);
+        let r = self.exe_call(&x);
+        self.pinning = pinning;
+        self.pins.truncate(p);
+        r?;
+        self.fits()
+    }
+
+    /** pop the current frame, giving what its body left on top. a body
+     * that leaves nothing returns nil */
+    fn leave(&mut self) -> Obj {
+        let r = self.stack.pop().unwrap_or(Obj::N);
+        if let Some(f) = self.frames.pop() {
+            self.at = f.caller;
+            trace!(self, exit(f.blk, &Ok(r)));
+        }
+        r
+    }
+
+    /** drop the frames above base after an error */
+    fn unwind<T>(&mut self, base: usize, r: &Res<T>) {
+        let Err(e) = r else {
+            return;
+        };
+        while self.frames.len() > base {
+            if let Some(f) = self.frames.pop() {
+                self.at = f.caller;
+                trace!(self, exit(f.blk, &Err(e.clone())));
             }
-            Blk(BlkType::Fun, Time::Deferred, i)
-                if self.bodies[i].vars <= self.stack.len() =>
-            {
-                dbgln!(
-                    alert,
-                    "deferred block with correct stack. jumping to {}",
-                    self.bodies[i].start
-                );
-                self.exe_body(i)
-            }
-            Blk(BlkType::Fun, Time::Deferred, _) => {
-                err_fmt!("deferred block called with too few arguments")
-            }
-            _ => unreachable!(),
-        }
-        .map_err(|e| {
-            heredoc!(
-                r#"
-                err while executing block {i}: {e}
-                tables:
-                {t}
-                vars:
-                {v}
-                "#,
-                t = self.fmt_tables(),
-                v = self.fmt_vars(),
-            )
-        })
+        }
     }
 
-    pub fn fmt_tables(&self) -> String {
-        self.tables
-            .1
+    /** the vars the functions running in frames bind themselves */
+    fn locals(&self, frames: &[Frame]) -> HashSet<usize> {
+        frames
             .iter()
-            .map(|(i, Table(t, a))| {
-                format!(
-                    "{i} /\n{}\n\t--------\n{}",
-                    t.iter()
-                        .map(|(k, v)| format!(
-                            "\t{} :\t{}",
-                            self.fmt(k),
-                            self.fmt(v)
-                        ))
-                        .collect::<Vec<_>>()
-                        .join("\n"),
-                    a.iter()
-                        .enumerate()
-                        .map(|(i, x)| format!("\t{i:3}: {}", self.fmt(x)))
-                        .collect::<Vec<_>>()
-                        .join("\n"),
-                )
+            .filter(|f| {
+                let b = self.prog.blocks.get(f.blk);
+                matches!(b, Some(Blk(_, Time::Deferred, _)))
             })
-            .collect::<Vec<_>>()
-            .join("\n")
+            .filter_map(|f| self.prog.bodies.get(f.body))
+            .flat_map(|b| BodyIterator::from(self.code(), b))
+            .filter_map(|x| match x {
+                Instr::Local(v) => Some(*v),
+                _ => None,
+            })
+            .collect()
     }
 
-    pub fn fmt_vars(&self) -> String {
-        self.vars
-            .iter()
-            .map(|(i, x)| format!("{i:3}: '{}'", self.fmt(x)))
-            .collect::<Vec<_>>()
-            .join("\n")
+    /** swap the running context for ctx. vars are global, so the locals
+     * of the functions on either side trade places too, and two
+     * coroutines running one function each keep their own */
+    fn swap(&mut self, ctx: &mut Ctx) {
+        let mut vs = self.locals(&self.frames);
+        vs.extend(self.locals(&ctx.frames));
+        vs.extend(ctx.vars.keys());
+        for v in vs {
+            let (x, y) = (self.vars.remove(&v), ctx.vars.remove(&v));
+            if let Some(x) = x {
+                ctx.vars.insert(v, x);
+            }
+            if let Some(y) = y {
+                self.vars.insert(v, y);
+            }
+        }
+        std::mem::swap(&mut self.stack, &mut ctx.stack);
+        std::mem::swap(&mut self.frames, &mut ctx.frames);
+        std::mem::swap(&mut self.natives, &mut ctx.natives);
     }
 
-    pub fn dump_tables(&self) {
-        dbgln!("{}", self.fmt_tables());
+    /** run coroutine c, with x as its arg or the value of its yield */
+    fn resume_coro(&mut self, c: usize, x: Obj) -> Res<()> {
+        if self.resumers.len() >= self.limits.depth {
+            return err!(self, Overflow);
+        }
+        let (f, status, mut ctx) = match self.coros.1.get_mut(&c) {
+            Some(co @ Coro { status: Status::Ready, .. })
+            | Some(co @ Coro { status: Status::Suspended, .. }) => {
+                let status = std::mem::replace(&mut co.status, Status::Running);
+                (co.f, status, std::mem::take(&mut co.ctx))
+            }
+            Some(_) => return err!(self, Resume),
+            None => return err!(self, NoCoro { coro: c }),
+        };
+        self.swap(&mut ctx);
+        self.resumers.push((c, ctx));
+        if status == Status::Suspended {
+            self.stack.push(x);
+            return Ok(());
+        }
+
+        /* a function that takes nothing isn't given x */
+        let args = match f {
+            Obj::Fun(b) if self.arity(b).unwrap_or(0) == 0 => Vec::new(),
+            _ => vec![Some(x)],
+        };
+        if let Err(e) = self.apply(f, args) {
+            let r = self.raised(Err(e));
+            self.abandon(self.resumers.len() - 1, &r);
+            return r;
+        }
+        /* nothing to run, so it's done already */
+        if self.frames.is_empty() {
+            let r = self.stack.pop().unwrap_or(Obj::N);
+            self.finish(r);
+        }
+        Ok(())
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::{
-        lisp::{Leaf, LeafType, Machine},
-        or_fatal,
-        vm::VM,
-    };
+    /** the running coroutine yielded x back to its resumer */
+    fn suspend(&mut self, x: Obj) {
+        let Some((c, mut ctx)) = self.resumers.pop() else {
+            return;
+        };
+        self.swap(&mut ctx);
+        if let Some(co) = self.coros.1.get_mut(&c) {
+            co.status = Status::Suspended;
+            co.ctx = ctx;
+        }
+        self.stack.push(x);
+    }
 
-    #[test]
-    fn expr() {
-        use Leaf::*;
-        for (i, (x, y)) in [
-            (D("+", &F(5.), &F(2.)), "7"),
-            (D("*", &F(5.), &F(2.)), "10"),
-            (D("-", &F(5.), &F(2.)), "3"),
-            (D("%", &F(5.), &F(2.)), "2.5"),
-            (
-                Fun {
-                    a: Vec::new(),
-                    v: vec![D("+", &F(1.), &F(1.))],
-                },
-                "{&1}",
-            ),
-            (
-                D(
-                    "@",
-                    &Fun {
-                        a: vec![("x", LeafType::F)],
-                        v: vec![D("+", &F(1.), &X("x"))],
-                    },
-                    &F(2.),
-                ),
-                "3",
-            ),
-            (
-                D(
-                    ".",
-                    &Fun {
-                        a: vec![("x", LeafType::F), ("y", LeafType::F)],
-                        v: vec![D("+", &X("x"), &X("y"))],
-                    },
-                    &A(vec![F(1.), F(1.)]),
-                ),
-                "2",
-            ),
-            (A(vec![Leaf::F(1.), Leaf::F(2.)]), "[|1, 2]"),
-            (M("!", &Leaf::F(3.)), "[|0, 1, 2]"),
-            (
-                D(
-                    ".",
-                    &Fun {
-                        a: vec![("x", LeafType::F), ("y", LeafType::F)],
-                        v: vec![A(vec![X("x"), X("y")])],
-                    },
-                    &M("!", &F(2.)),
-                ),
-                "[|0, 1]",
-            ),
-            (M("!", &D("+", &F(1.), &F(3.))), "[|0, 1, 2, 3]"),
-            (M("-", &M("-", &F(1.))), "1"),
+    /** the running coroutine returned x to its resumer */
+    fn finish(&mut self, x: Obj) {
+        let Some((c, mut ctx)) = self.resumers.pop() else {
+            return;
+        };
+        self.swap(&mut ctx);
+        if let Some(co) = self.coros.1.get_mut(&c) {
+            co.status = Status::Dead;
+        }
+        self.stack.push(x);
+    }
+
+    /** an error is escaping the coroutines resumed above depth, which
+     * kills them and hands control back to their resumers */
+    fn abandon<T>(&mut self, depth: usize, r: &Res<T>) {
+        while self.resumers.len() > depth {
+            self.unwind(0, r);
+            if let Some((c, mut ctx)) = self.resumers.pop() {
+                self.swap(&mut ctx);
+                if let Some(co) = self.coros.1.get_mut(&c) {
+                    co.status = Status::Dead;
+                }
+            }
+        }
+    }
+
+    /** use up one instruction's fuel, unless it's run out or execution
+     * was cancelled */
+    fn spend(&mut self) -> Res<()> {
+        let l = &self.limits;
+        if l.cancel.as_ref().is_some_and(|x| x.load(Ordering::Relaxed)) {
+            return err!(self, Cancelled);
+        }
+        if l.fuel.is_some_and(|n| self.ran >= n) {
+            return err!(self, Limit { limit: Limit::Fuel });
+        }
+        self.ran += 1;
+        Ok(())
+    }
+
+    /** fail if the tables have grown past their limits, even once the
+     * ones nothing can reach have been freed */
+    fn fits(&mut self) -> Res<()> {
+        if self.over().is_some() {
+            self.gc();
+        }
+        match self.over() {
+            Some(limit) => err!(self, Limit { limit }),
+            None => Ok(()),
+        }
+    }
+
+    /** how many projections and coroutines there are */
+    fn objs(&self) -> usize {
+        self.projs.1.len() + self.coros.1.len()
+    }
+
+    /** the limit the tables and objs have grown past, if any */
+    fn over(&self) -> Option<Limit> {
+        let l = &self.limits;
+        if l.tables.is_some_and(|n| self.tables.1.len() > n) {
+            Some(Limit::Tables)
+        } else if l.elems.is_some_and(|n| self.elems > n) {
+            Some(Limit::Elems)
+        } else if l.objs.is_some_and(|n| self.objs() > n) {
+            Some(Limit::Objs)
+        } else {
+            None
+        }
+    }
+
+    /** hand a fresh error to the tracer */
+    fn raised<T>(&mut self, r: Res<T>) -> Res<T> {
+        if let Err(e) = &r
+            && !self.unwinding
+        {
+            self.unwinding = true;
+            trace!(self, error(e));
+        }
+        r
+    }
+
+    /** run block i to the end. a script can't yield out of this, so
+     * builtins use it to call back into bytecode */
+    pub fn exe_block(&mut self, i: usize) -> Res<Obj> {
+        self.exe(i, false)
+    }
+
+    /** exe_block, but call says whether i is being called, as in enter */
+    fn exe(&mut self, i: usize, call: bool) -> Res<Obj> {
+        if self.runs >= self.limits.runs {
+            return err!(self, Overflow);
+        }
+        let base = self.frames.len();
+        self.natives += 1;
+        self.runs += 1;
+        let r = self.enter(i, call).and_then(|_| match self.frames.len() {
+            n if n <= base => self.pop(),
+            _ => match self.run(base)? {
+                Run::Finished(x) => Ok(x),
+                Run::Yielded(_) => err!(self, Yield),
+            },
+        });
+        self.natives -= 1;
+        self.runs -= 1;
+        r
+    }
+
+    /** run block i until it finishes or yields. a yield leaves the vm
+     * suspended until it's resumed */
+    pub fn start(&mut self, i: usize) -> Res<Run> {
+        if self.suspended() {
+            return err!(self, Resume);
+        }
+        self.enter(i, false)?;
+        if self.frames.is_empty() {
+            return Ok(Run::Finished(self.pop()?));
+        }
+        self.run(0)
+    }
+
+    /** carry on after a yield, with x as the value of the yield */
+    pub fn resume(&mut self, x: Obj) -> Res<Run> {
+        if !self.suspended() {
+            return err!(self, Resume);
+        }
+        self.stack.push(x);
+        self.run(0)
+    }
+
+    /** whether a script yielded and is waiting to be resumed */
+    #[inline]
+    pub fn suspended(&self) -> bool {
+        self.natives == 0 && !self.frames.is_empty()
+    }
+
+    /** enter block i. a block with a body to run gets a frame, which the
+     * run loop picks up, and any other block pushes its value now. call
+     * says i is being called with its args already pushed, so it runs
+     * even if it takes none; otherwise a function entered with nothing
+     * on the stack is just a reference to itself */
+    fn enter(&mut self, i: usize, call: bool) -> Res<()> {
+        self.unwinding = false;
+        trace!(self, enter(i));
+
+        let at = self.at;
+        let r = match self.prog.blocks.get(i).copied() {
+            Some(blk) => {
+                self.at.blk = i;
+                self.at.body = blk.idx();
+                self.enter_blk(i, blk, call)
+            }
+            None => err!(self, NoBlock { blk: i }),
+        };
+        let r = match r {
+            Ok(Next::Run(b)) => match self.push_frame(i, b, at) {
+                Ok(()) => return Ok(()),
+                Err(e) => Err(e),
+            },
+            Ok(Next::Value(x)) => Ok(x),
+            Err(e) => Err(e),
+        };
+        let r = self.raised(r);
+        self.at = at;
+
+        trace!(self, exit(i, &r));
+        self.stack.push(r?);
+        Ok(())
+    }
+
+    /** what entering block i comes to: a body to run, or its value */
+    fn enter_blk(&mut self, i: usize, blk: Blk, call: bool) -> Res<Next> {
+        match blk {
+            Blk(BlkType::Fun, Time::Immediate, i) => {
+                dbgln!(alert, "executing immediate body {i}");
+                Ok(Next::Run(i))
+            }
+            /* prep has already matched the args to the arity */
+            Blk(_, Time::Deferred, b) if call => Ok(Next::Run(b)),
+            Blk(_, Time::Deferred, _) if self.stack.is_empty() => {
+                dbgln!(alert, "deferred block with empty stack. returning.");
+                Ok(Next::Value(Obj::Fun(i)))
+            }
+            Blk(_, Time::Deferred, b) => match self.arity(i) {
+                Some(n) if n > self.stack.len() => {
+                    dbgln!(alert, "short stack. projecting");
+                    let v = std::mem::take(&mut self.stack);
+                    let v = v.into_iter().map(Some).collect();
+                    Ok(Next::Value(self.call(Obj::Fun(i), v)?))
+                }
+                Some(_) => Ok(Next::Run(b)),
+                None => err!(self, NoBlock { blk: b }),
+            },
+            Blk(t, Time::Immediate, i) => {
+                let n = if t == BlkType::One { 1 } else { 2 };
+                if self.stack.len() < n {
+                    err!(self, Arity {
+                        expected: n,
+                        actual: self.stack.len()
+                    })
+                } else {
+                    Ok(Next::Run(i))
+                }
+            }
+        }
+    }
+
+    fn push_frame(&mut self, blk: usize, body: usize, caller: Loc) -> Res<()> {
+        if self.frames.len() >= self.limits.depth {
+            return err!(self, Overflow);
+        }
+        let Some(b) = self.prog.bodies.get(body) else {
+            return err!(self, NoBlock { blk: body });
+        };
+        self.frames.push(Frame {
+            blk,
+            body,
+            pc: b.start,
+            caller,
+        });
+        Ok(())
+    }
+
+    /** how many args block f wants, if it's something that takes args */
+    fn arity(&self, f: usize) -> Option<usize> {
+        match self.prog.blocks.get(f) {
+            Some(Blk(BlkType::One, ..)) => Some(1),
+            Some(Blk(BlkType::Two, ..)) => Some(2),
+            Some(Blk(BlkType::Fun, Time::Deferred, b)) => {
+                self.prog.bodies.get(*b).map(|x| x.vars)
+            }
+            _ => None,
+        }
+    }
+
+    /** whether block f binds n args. a function body binds each with a
+     * Local of its own before anything else, so one that says it takes
+     * more than it binds is lying, and shouldn't get room made for them */
+    fn binds(&self, f: usize, n: usize) -> bool {
+        let Some(Blk(BlkType::Fun, Time::Deferred, b)) =
+            self.prog.blocks.get(f)
+        else {
+            return true;
+        };
+        let Some(b) = self.prog.bodies.get(*b) else {
+            return false;
+        };
+        let code = self.prog.instrs.get(b.start..).unwrap_or_default();
+        let k = code.iter().take(n);
+        k.filter(|x| matches!(x, Instr::Local(_))).count() == n
+    }
+
+    /** the items of vector x */
+    fn items(&self, x: Obj) -> Res<Vec<Obj>> {
+        match x {
+            Obj::T(t) => Ok(self.get_table(t)?.vec().to_vec()),
+            x => err!(self, Type {
+                expected: ObjType::T,
+                actual: x.ty()
+            }),
+        }
+    }
+
+    fn mk_vec(&mut self, v: Vec<Obj>) -> Obj {
+        Obj::T(self.add_table(Table(Dict::default(), v)))
+    }
+
+    /** the items of x, or just x if it isn't a vector */
+    fn list(&self, x: Obj) -> Res<Vec<Obj>> {
+        match x {
+            Obj::T(_) => self.items(x),
+            x => Ok(vec![x]),
+        }
+    }
+
+    /** x as a string, if it's a nonempty vector of chars */
+    pub fn string(&self, x: Obj) -> Option<String> {
+        let Obj::T(i) = x else {
+            return None;
+        };
+        let t = self.get_table(i).ok()?;
+        if !t.0.is_empty() || t.1.is_empty() {
+            return None;
+        }
+        t.1.iter()
+            .map(|x| match x {
+                Obj::C(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn mk_str(&mut self, s: &str) -> Obj {
+        let v = s.chars().map(Obj::C).collect();
+        self.mk_vec(v)
+    }
+
+    /** the text of x, where an empty vector is an empty string */
+    fn text(&self, x: Obj) -> Res<String> {
+        match x {
+            Obj::C(c) => Ok(c.to_string()),
+            Obj::T(_) if self.items(x)?.is_empty() => Ok(String::new()),
+            x => match self.string(x) {
+                Some(s) => Ok(s),
+                None => err!(self, Type {
+                    expected: ObjType::C,
+                    actual: x.ty()
+                }),
+            },
+        }
+    }
+
+    /** $x: x as a string. strings are left alone, and other vectors
+     * become a vector of strings */
+    fn str(&mut self, x: Obj, d: usize) -> Res<Obj> {
+        match x {
+            _ if self.string(x).is_some() => Ok(x),
+            Obj::T(_) => {
+                let d = self.nest(d)?;
+                let v = self
+                    .items(x)?
+                    .into_iter()
+                    .map(|x| self.str(x, d))
+                    .collect::<Res<Vec<_>>>()?;
+                Ok(self.mk_vec(v))
+            }
+            Obj::S(x) => {
+                let s = self.prog.syms.name(x).to_string();
+                Ok(self.mk_str(&s))
+            }
+            x => {
+                let s = self.fmt(&x);
+                Ok(self.mk_str(&s))
+            }
+        }
+    }
+
+    /** the number written in string x, or each string in x. integers
+     * too big to be one, which includes 0N's usize::MAX, are floats */
+    fn parse(&mut self, x: Obj, d: usize) -> Res<Obj> {
+        if let Ok(s) = self.text(x) {
+            return match (s.parse::<usize>(), s.parse::<f64>()) {
+                _ if s == "0N" => Ok(Obj::NU),
+                _ if s == "0n" => Ok(Obj::NF),
+                (Ok(x), _) if x != usize::MAX => Ok(Obj::U(x)),
+                (_, Ok(x)) => Ok(Obj::F(x)),
+                _ => err!(self, Parse { text: s }),
+            };
+        }
+        let d = self.nest(d)?;
+        let v = self
+            .items(x)?
+            .into_iter()
+            .map(|x| self.parse(x, d))
+            .collect::<Res<Vec<_>>>()?;
+        Ok(self.mk_vec(v))
+    }
+
+    /** f of x, going into vectors item by item. f gives None for
+     * things it can't take, which should have been a t */
+    fn atomic1(
+        &mut self,
+        x: Obj,
+        t: ObjType,
+        f: &dyn Fn(Obj) -> Option<Obj>,
+        d: usize,
+    ) -> Res<Obj> {
+        match x {
+            Obj::T(_) => {
+                let d = self.nest(d)?;
+                let v = self
+                    .items(x)?
+                    .into_iter()
+                    .map(|x| self.atomic1(x, t, f, d))
+                    .collect::<Res<Vec<_>>>()?;
+                Ok(self.mk_vec(v))
+            }
+            x => match f(x) {
+                Some(r) => Ok(r),
+                None => err!(self, Type {
+                    expected: t,
+                    actual: x.ty()
+                }),
+            },
+        }
+    }
+
+    /** f of x and y, going into vectors item by item. a scalar goes
+     * with every item of a vector, and two vectors must be the same
+     * length */
+    fn atomic2(
+        &mut self,
+        x: Obj,
+        y: Obj,
+        f: &dyn Fn(Obj, Obj) -> Option<Obj>,
+        d: usize,
+    ) -> Res<Obj> {
+        let d = match (x, y) {
+            (Obj::T(_), _) | (_, Obj::T(_)) => self.nest(d)?,
+            _ => d,
+        };
+        let v = match (x, y) {
+            (Obj::T(_), Obj::T(_)) => {
+                let (x, y) = (self.items(x)?, self.items(y)?);
+                if x.len() != y.len() {
+                    return err!(self, Length {
+                        expected: x.len(),
+                        actual: y.len()
+                    });
+                }
+                x.into_iter()
+                    .zip(y)
+                    .map(|(x, y)| self.atomic2(x, y, f, d))
+                    .collect::<Res<Vec<_>>>()?
+            }
+            (Obj::T(_), y) => self
+                .items(x)?
+                .into_iter()
+                .map(|x| self.atomic2(x, y, f, d))
+                .collect::<Res<Vec<_>>>()?,
+            (x, Obj::T(_)) => self
+                .items(y)?
+                .into_iter()
+                .map(|y| self.atomic2(x, y, f, d))
+                .collect::<Res<Vec<_>>>()?,
+            (x, y) => match f(x, y) {
+                Some(r) => return Ok(r),
+                /* blame whichever one isn't a number */
+                None => err!(self, Type {
+                    expected: ObjType::F,
+                    actual: if num(x) { y.ty() } else { x.ty() }
+                })?,
+            },
+        };
+        Ok(self.mk_vec(v))
+    }
+
+    /** how deep a walk into one more table from depth d is. tables
+     * nest too deep to walk once that's past the depth limit */
+    fn nest(&self, d: usize) -> Res<usize> {
+        match d < self.limits.depth {
+            true => Ok(d + 1),
+            false => err!(self, Overflow),
+        }
+    }
+
+    /** fail if x is table t or holds it, since then putting x in t
+     * would leave t holding itself */
+    fn acyclic(&self, t: usize, x: Obj) -> Res<()> {
+        let (mut todo, mut tables, mut projs) =
+            (vec![x], HashSet::new(), HashSet::new());
+        while let Some(x) = todo.pop() {
+            match x {
+                Obj::T(i) if i == t => return err!(self, Cycle),
+                Obj::T(i) if tables.insert(i) => {
+                    if let Some(Table(d, v)) = self.tables.1.get(&i) {
+                        todo.extend(d.keys().chain(d.values()));
+                        todo.extend(v.iter().copied());
+                    }
+                }
+                Obj::P(i) if projs.insert(i) => {
+                    if let Some(Proj(_, v)) = self.projs.1.get(&i) {
+                        todo.extend(v.iter().flatten());
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /** x ~ y: the same type and the same value, all the way down */
+    fn matches(&self, x: Obj, y: Obj, d: usize) -> Res<bool> {
+        let (Obj::T(a), Obj::T(b)) = (x, y) else {
+            return Ok(Key::from(x) == Key::from(y));
+        };
+        let d = self.nest(d)?;
+        let (a, b) = (self.get_table(a)?, self.get_table(b)?);
+        if a.0.len() != b.0.len() || a.1.len() != b.1.len() {
+            return Ok(false);
+        }
+        for ((k, v), (j, w)) in a.0.pairs().iter().zip(b.0.pairs()) {
+            if !self.matches(*k, *j, d)? || !self.matches(*v, *w, d)? {
+                return Ok(false);
+            }
+        }
+        for (x, y) in a.1.iter().zip(&b.1) {
+            if !self.matches(*x, *y, d)? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /** table t at a single index or key i */
+    fn at(&self, t: usize, i: Obj) -> Res<Obj> {
+        let t = self.get_table(t)?;
+        match i {
+            Obj::U(n) if !t.keyed(i) => match t.vec().get(n) {
+                Some(x) => Ok(*x),
+                None => err!(self, Index {
+                    index: n,
+                    len: t.vec().len()
+                }),
+            },
+            i => match t.get(i) {
+                Some(x) => Ok(x),
+                None => err!(self, NoKey { key: i }),
+            },
+        }
+    }
+
+    /** the null of table t's items, going by its first value */
+    fn null_in(&self, t: usize) -> Res<Obj> {
+        let t = self.get_table(t)?;
+        let x = t.0.values().next().or(t.vec().first().copied());
+        Ok(x.map_or(Obj::N, |x| Obj::null(x.ty())))
+    }
+
+    /** set table t at a single index or key i to x */
+    fn set_at(&mut self, t: usize, i: Obj, x: Obj) -> Res<()> {
+        self.acyclic(t, i)?;
+        self.acyclic(t, x)?;
+        let at = self.loc();
+        self.edit_table(t, |t| match i {
+            Obj::U(n) if !t.keyed(i) => {
+                let len = t.vec().len();
+                match t.vec_mut().get_mut(n) {
+                    Some(y) => *y = x,
+                    None => Err(Error::Index { index: n, len, at })?,
+                }
+                Ok(())
+            }
+            /* a key can't go into a vector */
+            i if !t.vec().is_empty() => Err(Error::Type {
+                expected: ObjType::U,
+                actual: i.ty(),
+                at,
+            }),
+            i => {
+                t.0.insert(i, x);
+                Ok(())
+            }
+        })?
+    }
+
+    /** whether i indexes table t as a whole, rather than by its items */
+    fn is_key(&self, t: usize, i: Obj) -> Res<bool> {
+        Ok(!matches!(i, Obj::T(_)) || self.get_table(t)?.get(i).is_some())
+    }
+
+    /** x indexed by each item of path in turn. a vector of indices maps
+     * over the rest of the path, and anything that isn't a table is
+     * called with what's left */
+    fn dig(&mut self, x: Obj, path: &[Obj], d: usize) -> Res<Obj> {
+        let Some((i, rest)) = path.split_first() else {
+            return Ok(x);
+        };
+        let d = self.nest(d)?;
+        let Obj::T(t) = x else {
+            return self.call(x, path.iter().map(|x| Some(*x)).collect());
+        };
+        if self.is_key(t, *i)? {
+            /* reading past the end or a missing key gives a null */
+            let y = match self.at(t, *i) {
+                Err(Error::Index { .. } | Error::NoKey { .. }) => {
+                    self.null_in(t)?
+                }
+                y => y?,
+            };
+            return self.dig(y, rest, d);
+        }
+        let v = self
+            .items(*i)?
+            .into_iter()
+            .map(|i| self.dig(x, &[&[i], rest].concat(), d))
+            .collect::<Res<Vec<_>>>()?;
+        Ok(self.mk_vec(v))
+    }
+
+    /** set table t at i, or at each index in vector i, to the result of
+     * f on the table, the index and where the index is in i */
+    fn amend(
+        &mut self,
+        t: usize,
+        i: Obj,
+        f: &dyn Fn(&mut Self, usize, Obj, usize) -> Res<Obj>,
+    ) -> Res<()> {
+        let v = if self.is_key(t, i)? {
+            vec![i]
+        } else {
+            self.items(i)?
+        };
+        for (n, i) in v.into_iter().enumerate() {
+            let x = f(self, t, i, n)?;
+            self.set_at(t, i, x)?;
+        }
+        Ok(())
+    }
+
+    /** the status of coroutine c */
+    pub fn status(&self, c: usize) -> Res<Status> {
+        match self.coros.1.get(&c) {
+            Some(x) => Ok(x.status),
+            None => err!(self, NoCoro { coro: c }),
+        }
+    }
+
+    /** free the tables, projections and coroutines nothing can reach,
+     * giving how many tables there were. the roots are the stack, the
+     * variables, the code's constants, the running coroutines and the
+     * stacks of their resumers, and whatever the instructions partway
+     * through running have pinned */
+    pub fn gc(&mut self) -> usize {
+        let running = self.resumers.iter().map(|x| Obj::Co(x.0));
+        let mut todo = self
+            .stack
+            .iter()
+            .chain(self.vars.values())
+            .chain(self.yielded.iter())
+            .chain(self.resumers.iter().flat_map(|x| {
+                x.1.stack.iter().chain(x.1.vars.values())
+            }))
+            .chain(self.pins.iter())
+            .copied()
+            .chain(running)
+            .collect::<Vec<_>>();
+        todo.extend(self.prog.instrs.iter().filter_map(|x| match x {
+            Instr::Push(x) => Some(*x),
+            _ => None,
+        }));
+
+        let (mut tables, mut projs, mut coros) =
+            (HashSet::new(), HashSet::new(), HashSet::new());
+        while let Some(x) = todo.pop() {
+            match x {
+                Obj::T(i) if tables.insert(i) => {
+                    if let Some(Table(d, v)) = self.tables.1.get(&i) {
+                        todo.extend(d.keys().chain(d.values()));
+                        todo.extend(v.iter().copied());
+                    }
+                }
+                Obj::P(i) if projs.insert(i) => {
+                    if let Some(Proj(_, v)) = self.projs.1.get(&i) {
+                        todo.extend(v.iter().flatten());
+                    }
+                }
+                Obj::Co(i) if coros.insert(i) => {
+                    if let Some(c) = self.coros.1.get(&i) {
+                        todo.push(c.f);
+                        todo.extend(c.ctx.stack.iter().copied());
+                        todo.extend(c.ctx.vars.values().copied());
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        let dead = self
+            .tables
+            .1
+            .keys()
+            .filter(|x| !tables.contains(x))
+            .copied()
+            .collect::<Vec<_>>();
+        dead.iter().for_each(|x| self.rm_table(x));
+        self.projs.1.retain(|i, _| projs.contains(i));
+        self.coros.1.retain(|i, _| coros.contains(i));
+        dead.len()
+    }
+
+    /** fail if the vm is partway through something it can't be saved or
+     * restored in the middle of */
+    fn settled(&self) -> Res<()> {
+        if self.natives > 0 || !self.resumers.is_empty() {
+            return err!(self, Snapshot {
+                msg: "the vm is inside a builtin or coroutine".to_string()
+            });
+        }
+        Ok(())
+    }
+
+    /** the vm's state as bytes: its stack, variables, tables,
+     * projections and coroutines, and its frames if it's suspended.
+     * restore loads them into a vm running the same program */
+    pub fn snapshot(&self) -> Res<Vec<u8>> {
+        self.settled()?;
+        let mut w = Writer::new(fingerprint(&self.prog), &self.prog.syms);
+        w.objs(&self.stack);
+
+        w.vars(&self.vars);
+
+        let mut tables = self.tables.1.iter().collect::<Vec<_>>();
+        tables.sort_by_key(|x| x.0);
+        w.usize(self.tables.0);
+        w.usize(tables.len());
+        for (i, Table(d, v)) in tables {
+            w.usize(*i);
+            w.usize(d.len());
+            for (k, x) in d.pairs() {
+                w.obj(*k);
+                w.obj(*x);
+            }
+            w.objs(v);
+        }
+
+        let mut projs = self.projs.1.iter().collect::<Vec<_>>();
+        projs.sort_by_key(|x| x.0);
+        w.usize(self.projs.0);
+        w.usize(projs.len());
+        for (i, Proj(b, v)) in projs {
+            w.usize(*i);
+            w.usize(*b);
+            w.usize(v.len());
+            for x in v {
+                match x {
+                    Some(x) => {
+                        w.u8(1);
+                        w.obj(*x);
+                    }
+                    None => w.u8(0),
+                }
+            }
+        }
+
+        let mut coros = self.coros.1.iter().collect::<Vec<_>>();
+        coros.sort_by_key(|x| x.0);
+        w.usize(self.coros.0);
+        w.usize(coros.len());
+        for (i, c) in coros {
+            w.usize(*i);
+            w.obj(c.f);
+            w.status(c.status);
+            w.objs(&c.ctx.stack);
+            w.frames(&c.ctx.frames);
+            w.vars(&c.ctx.vars);
+        }
+
+        w.frames(&self.frames);
+        w.at(self.at);
+        Ok(w.0)
+    }
+
+    /** replace the vm's state with snapshot b. b has to have been taken
+     * from a vm running this same program. nothing changes if it fails */
+    pub fn restore(&mut self, b: &[u8]) -> Res<()> {
+        self.settled()?;
+        let p = self.prog.clone();
+        let (mut r, f) = Reader::new(b, &p.syms)?;
+        if f != fingerprint(&p) {
+            return err!(self, Snapshot {
+                msg: "taken from a different program".to_string()
+            });
+        }
+        let stack = r.objs()?;
+        let vars = r.vars()?;
+
+        let n = r.usize()?;
+        let mut tables = HashMap::new();
+        for _ in 0..r.count()? {
+            let i = r.usize()?;
+            let mut d = Dict::default();
+            for _ in 0..r.count()? {
+                d.insert(r.obj()?, r.obj()?);
+            }
+            tables.insert(i, Table(d, r.objs()?));
+        }
+
+        let np = r.usize()?;
+        let mut projs = HashMap::new();
+        for _ in 0..r.count()? {
+            let i = r.usize()?;
+            let b = r.usize()?;
+            let mut v = Vec::new();
+            for _ in 0..r.count()? {
+                v.push(match r.u8()? {
+                    0 => None,
+                    _ => Some(r.obj()?),
+                });
+            }
+            projs.insert(i, Proj(b, v));
+        }
+
+        let nc = r.usize()?;
+        let mut coros = HashMap::new();
+        for _ in 0..r.count()? {
+            let i = r.usize()?;
+            coros.insert(i, Coro {
+                f: r.obj()?,
+                status: r.status()?,
+                ctx: Ctx {
+                    stack: r.objs()?,
+                    frames: r.frames()?,
+                    natives: 0,
+                    vars: r.vars()?,
+                },
+            });
+        }
+
+        let frames = r.frames()?;
+        let at = r.at()?;
+        r.end()?;
+
+        self.elems = tables.values().map(Table::size).sum();
+        self.stack = stack;
+        self.vars = vars;
+        self.tables = (n, tables);
+        self.projs = (np, projs);
+        self.coros = (nc, coros);
+        self.frames = frames;
+        self.at = at;
+        self.yielded = None;
+        self.unwinding = false;
+        Ok(())
+    }
+
+    /** add projection p, pinned like a new table */
+    fn add_proj(&mut self, p: Proj) -> usize {
+        let n = self.projs.0;
+        if self.pinning {
+            self.pins.push(Obj::P(n));
+        }
+        self.projs.1.insert(n, p);
+        self.projs.0 += 1;
+        n
+    }
+
+    /** add coroutine c, pinned like a new table */
+    fn add_coro(&mut self, c: Coro) -> usize {
+        let n = self.coros.0;
+        if self.pinning {
+            self.pins.push(Obj::Co(n));
+        }
+        self.coros.1.insert(n, c);
+        self.coros.0 += 1;
+        n
+    }
+
+    fn get_proj(&self, p: usize) -> Res<&Proj> {
+        match self.projs.1.get(&p) {
+            Some(x) => Ok(x),
+            None => err!(self, NoProj { proj: p }),
+        }
+    }
+
+    /** call f with args, where None is a hole. the args fill f's holes
+     * in order; if any are left, the result is a new projection. no
+     * function takes more args than it's missing */
+    fn call(&mut self, f: Obj, args: Vec<Option<Obj>>) -> Res<Obj> {
+        let r = self.prep(f, args).and_then(|x| match x {
+            Next::Run(b) => self.exe(b, true),
+            Next::Value(x) => Ok(x),
+        })?;
+        if self.pinning {
+            self.pins.push(r);
+        }
+        Ok(r)
+    }
+
+    /** call f from the run loop, so it gets a frame of its own rather
+     * than a nested run, and can yield */
+    fn apply(&mut self, f: Obj, args: Vec<Option<Obj>>) -> Res<()> {
+        match self.prep(f, args)? {
+            Next::Run(b) => self.enter(b, true),
+            Next::Value(x) => {
+                self.stack.push(x);
+                Ok(())
+            }
+        }
+    }
+
+    /** get ready to call f with args: push them and give the block to
+     * run, or give the value if there's nothing to run */
+    fn prep(&mut self, f: Obj, args: Vec<Option<Obj>>) -> Res<Next> {
+        let (b, mut held) = match f {
+            /* calling a table indexes it */
+            Obj::T(_) if args.iter().all(|x| x.is_some()) => {
+                let path = args.into_iter().flatten().collect::<Vec<_>>();
+                return Ok(Next::Value(self.dig(f, &path, 0)?));
+            }
+            Obj::Fun(b) => {
+                let n = self.arity(b).unwrap_or(0);
+                if !self.binds(b, n) {
+                    return err!(self, Arity {
+                        expected: n,
+                        actual: args.len()
+                    });
+                }
+                (b, vec![None; n])
+            }
+            Obj::P(p) => {
+                let Proj(b, v) = self.get_proj(p)?;
+                (*b, v.clone())
+            }
+            x => err!(self, Type {
+                expected: ObjType::Fun,
+                actual: x.ty()
+            })?,
+        };
+
+        let n = held.iter().filter(|x| x.is_none()).count();
+        let k = args.len();
+        let mut args = args.into_iter();
+        held.iter_mut()
+            .filter(|x| x.is_none())
+            .zip(&mut args)
+            .for_each(|(x, y)| *x = y);
+        if args.next().is_some() {
+            return err!(self, Arity {
+                expected: n,
+                actual: k
+            });
+        }
+        if held.iter().all(|x| x.is_none()) && !held.is_empty() {
+            return Ok(Next::Value(f));
+        }
+        if held.iter().any(|x| x.is_none()) {
+            let p = self.add_proj(Proj(b, held));
+            return Ok(Next::Value(Obj::P(p)));
+        }
+
+        held.into_iter().flatten().for_each(|x| self.stack.push(x));
+        Ok(Next::Run(b))
+    }
+
+    /** format an error along with the tables and variables at the time */
+    pub fn report(&self, e: &Error) -> String {
+        heredoc!(
+            r#"
+            err while executing block {b}: {e}
+            tables:
+            {t}
+            vars:
+            {v}
+            "#,
+            b = e.loc().blk,
+            t = self.fmt_tables(),
+            v = self.fmt_vars(),
+        )
+    }
+
+    pub fn fmt_tables(&self) -> String {
+        self.tables
+            .1
+            .iter()
+            .map(|(i, Table(t, a))| {
+                format!(
+                    "{i} /\n{}\n\t--------\n{}",
+                    t.pairs()
+                        .iter()
+                        .map(|(k, v)| format!(
+                            "\t{} :\t{}",
+                            self.fmt(k),
+                            self.fmt(v)
+                        ))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                    a.iter()
+                        .enumerate()
+                        .map(|(i, x)| format!("\t{i:3}: {}", self.fmt(x)))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn fmt_vars(&self) -> String {
+        self.vars
+            .iter()
+            .map(|(i, x)| format!("{i:3}: '{}'", self.fmt(x)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    pub fn dump_tables(&self) {
+        dbgln!("{}", self.fmt_tables());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        bc::{Blk, BlkType, Body, Instr, Obj, ObjType, Program, Time},
+        cov::Coverage,
+        dbg::{Break, Debugger, Step, Stop, Why},
+        err::{Error, Limit, Loc},
+        link::link,
+        lisp::{self, Leaf, LeafType, Machine},
+        or_fatal,
+        prof::Profiler,
+        sym::Syms,
+        trace::Json,
+        vm::{Limits, MAX_DEPTH, Machine as _, Run, Status, VM},
+    };
+    use std::{
+        cell::RefCell,
+        rc::Rc,
+        sync::{
+            Arc,
+            atomic::{AtomicBool, Ordering},
+        },
+        thread,
+    };
+
+    fn body(start: usize, vars: usize) -> Body {
+        Body {
+            start,
+            vars,
+            names: Vec::new(),
+            export: Vec::new(),
+        }
+    }
+
+    /** a check that an error matches a pattern of Error's variants */
+    macro_rules! is {
+        ($($p:tt)*) => {
+            (|e: &Error| matches!(e, Error::$($p)*)) as fn(&Error) -> bool
+        };
+    }
+
+    /** what running a source should give: the result as fmt prints
+     * it, or an error that passes a check made with is! */
+    type Want = Result<&'static str, fn(&Error) -> bool>;
+
+    /** compile src as a unit, run it and check it gave e. gives back
+     * the vm, for anything else to look at */
+    fn check(src: &str, e: Want) -> VM {
+        let v = or_fatal(lisp::read_all(src));
+        let mut m = Machine::new();
+        let b = or_fatal(m.unit("main", &v));
+        let mut vm = VM::new(m.program());
+        match (vm.exe_block(b), e) {
+            (Ok(r), Ok(e)) => assert_eq!(vm.fmt(&r), e, "{src}"),
+            (Err(r), Err(e)) => assert!(e(&r), "{src}: {r:?}"),
+            (r, _) => panic!("{src}: {r:?}"),
+        }
+        vm
+    }
+
+    #[test]
+    fn expr() {
+        use Leaf::*;
+        for (i, (x, y)) in [
+            (D("+", Box::new(F(5.)), Box::new(F(2.))), "7"),
+            (D("*", Box::new(F(5.)), Box::new(F(2.))), "10"),
+            (D("-", Box::new(F(5.)), Box::new(F(2.))), "3"),
+            (D("%", Box::new(F(5.)), Box::new(F(2.))), "2.5"),
+            (
+                Fun {
+                    a: Vec::new(),
+                    v: vec![D("+", Box::new(F(1.)), Box::new(F(1.)))],
+                },
+                "{&1}",
+            ),
+            (
+                D(
+                    "@",
+                    Box::new(Fun {
+                        a: vec![("x", LeafType::F)],
+                        v: vec![D("+", Box::new(F(1.)), Box::new(X("x")))],
+                    }),
+                    Box::new(F(2.)),
+                ),
+                "3",
+            ),
+            (
+                D(
+                    ".",
+                    Box::new(Fun {
+                        a: vec![("x", LeafType::F), ("y", LeafType::F)],
+                        v: vec![D("+", Box::new(X("x")), Box::new(X("y")))],
+                    }),
+                    Box::new(A(vec![F(1.), F(1.)])),
+                ),
+                "2",
+            ),
+            (A(vec![Leaf::F(1.), Leaf::F(2.)]), "[|1, 2]"),
+            (M("!", Box::new(Leaf::F(3.))), "[|0, 1, 2]"),
+            (
+                D(
+                    ".",
+                    Box::new(Fun {
+                        a: vec![("x", LeafType::F), ("y", LeafType::F)],
+                        v: vec![A(vec![X("x"), X("y")])],
+                    }),
+                    Box::new(M("!", Box::new(F(2.)))),
+                ),
+                "[|0, 1]",
+            ),
+            (
+                M("!", Box::new(D("+", Box::new(F(1.)), Box::new(F(3.))))),
+                "[|0, 1, 2, 3]",
+            ),
+            (M("-", Box::new(M("-", Box::new(F(1.))))), "1"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            println!(" === TEST {i} ===");
+            println!("{x:?}");
+            let mut m = Machine::new();
+            let b = or_fatal(m.compile(&x));
+            m.dump();
+            println!("beginning execution at block {b}");
+
+            let mut vm = VM::new(m.program());
+            let e = or_fatal(vm.exe_block(b));
+
+            println!("tables:");
+            vm.dump_tables();
+
+            assert_eq!(vm.fmt(&e), y.to_string())
+        }
+    }
+
+    #[test]
+    fn errors() {
+        use Instr::*;
+        let at = |instr, blk, body| Loc { instr, blk, body };
+        for (i, (code, blocks, bodies, e)) in [
+            (
+                vec![Push(Obj::F(1.)), Push(Obj::Fun(0)), AddF, Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::Type {
+                    expected: ObjType::F,
+                    actual: ObjType::Fun,
+                    at: at(2, 0, 0),
+                },
+            ),
+            (
+                vec![Push(Obj::F(1.)), NegF, Pop, NegF, Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::Underflow { at: at(3, 0, 0) },
+            ),
+            (
+                vec![Pop, Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::Underflow { at: at(0, 0, 0) },
+            ),
+            (
+                vec![Vec(0), Local(0), VecPop(0), Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 1)],
+                Error::Underflow { at: at(2, 0, 0) },
+            ),
+            (
+                vec![Load(7), Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::NoVar {
+                    var: 7,
+                    at: at(0, 0, 0),
+                },
+            ),
+            (
+                vec![Push(Obj::F(1.)), Jmp(3), Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::NoBlock {
+                    blk: 3,
+                    at: at(1, 0, 0),
+                },
+            ),
+            (
+                vec![Push(Obj::F(1.)), Jmp(1), Ret, Local(0), Local(1), Ret],
+                vec![
+                    Blk(BlkType::Fun, Time::Immediate, 0),
+                    Blk(BlkType::Two, Time::Immediate, 1),
+                ],
+                vec![body(0, 0), body(3, 2)],
+                Error::Arity {
+                    expected: 2,
+                    actual: 1,
+                    at: at(1, 1, 1),
+                },
+            ),
+            (
+                vec![Push(Obj::F(1.)), LJmpNZ(4), Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::NoLabel {
+                    label: 4,
+                    at: at(1, 0, 0),
+                },
+            ),
+            (
+                vec![Push(Obj::U(0)), LJmpZ(5), Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::NoLabel {
+                    label: 5,
+                    at: at(1, 0, 0),
+                },
+            ),
+            (
+                vec![Push(Obj::Fun(0)), Proj(70, 0), Ret],
+                vec![Blk(BlkType::Fun, Time::Immediate, 0)],
+                vec![body(0, 0)],
+                Error::Invalid {
+                    instr: Proj(70, 0),
+                    at: at(1, 0, 0),
+                },
+            ),
+            (
+                vec![Push(Obj::Fun(1)), Push(Obj::U(1)), Apply1, Ret, Ret],
+                vec![
+                    Blk(BlkType::Fun, Time::Immediate, 0),
+                    Blk(BlkType::Fun, Time::Deferred, 1),
+                ],
+                vec![body(0, 0), body(4, usize::MAX)],
+                Error::Arity {
+                    expected: usize::MAX,
+                    actual: 1,
+                    at: at(2, 0, 0),
+                },
+            ),
+            /* a body that binds fewer args than it says it takes, in
+             * code long enough to hold them all */
+            (
+                vec![
+                    Push(Obj::Fun(1)),
+                    Push(Obj::U(1)),
+                    Apply1,
+                    Ret,
+                    Local(0),
+                    Local(1),
+                    Ret,
+                    Nop,
+                    Nop,
+                ],
+                vec![
+                    Blk(BlkType::Fun, Time::Immediate, 0),
+                    Blk(BlkType::Fun, Time::Deferred, 1),
+                ],
+                vec![body(0, 0), body(4, 3)],
+                Error::Arity {
+                    expected: 3,
+                    actual: 1,
+                    at: at(2, 0, 0),
+                },
+            ),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            println!(" === TEST {i} ===");
+            let mut vm = VM::new(Program::new(code, blocks, bodies));
+            let r = vm.exe_block(0);
+            if let Err(e) = &r {
+                println!("{}", vm.report(e));
+            }
+            assert_eq!(r, Err(e));
+        }
+
+        /* nop and break do nothing without a debugger */
+        let code = vec![
+            Push(Obj::U(1)),
+            Nop,
+            Break,
+            Vec(1),
+            Local(0),
+            VecPop(0),
+            Load(0),
+            Ret,
+        ];
+        let blocks = vec![Blk(BlkType::Fun, Time::Immediate, 0)];
+        let mut vm = VM::new(Program::new(code, blocks, vec![body(0, 1)]));
+        let r = or_fatal(vm.exe_block(0));
+        assert_eq!(vm.fmt(&r), "[|]");
+
+        let mut m = Machine::new();
+        assert!(matches!(
+            m.compile(&Leaf::X("nope")),
+            Err(Error::Compile { .. })
+        ));
+    }
+
+    /** xorshift, so the fuzzer is reproducible without pulling in rand */
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.next() % n
+        }
+
+        /** mostly small, but now and then big enough to overflow
+         * anything that trusts it */
+        fn size(&mut self) -> usize {
+            match self.below(16) {
+                0 => usize::MAX - self.below(2),
+                1 => 1 << self.below(usize::BITS as usize),
+                2 => self.below(70),
+                _ => self.below(6),
+            }
+        }
+
+        fn obj(&mut self) -> Obj {
+            match self.below(8) {
+                0 => Obj::C('x'),
+                5 => Obj::N,
+                6 => Obj::Co(self.below(2)),
+                1 => Obj::F(self.below(3) as f64),
+                2 => Obj::U(self.below(3)),
+                3 => Obj::T(self.below(4)),
+                4 => Obj::S(self.below(2)),
+                _ => Obj::Fun(self.below(4)),
+            }
+        }
+
+        fn instr(&mut self) -> Instr {
+            use Instr::*;
+            let n = self.size();
+            match self.below(78) {
+                0 => Push(self.obj()),
+                1 => Pop,
+                2 => Local(n),
+                3 => Load(n),
+                4 => Label(n),
+                5 => AddF,
+                6 => SubF,
+                7 => MulF,
+                8 => DivF,
+                9 => NegF,
+                10 => CmpF,
+                11 => Table(n),
+                12 => Vec(n),
+                13 => VecFull,
+                14 => VecPush(n),
+                15 => VecLast(n),
+                16 => VecPop(n),
+                17 => Apply0,
+                18 => Apply1,
+                19 => ApplyN,
+                20 => Dup,
+                21 => Swap2,
+                22 => PopVec,
+                23 => Ret,
+                24 => Break,
+                25 => Nop,
+                26 => Jmp(n),
+                27 => JmpZ(n),
+                28 => LJmpZ(n),
+                29 => LJmpNZ(n),
+                30 => Apply2,
+                31 => Proj(n, self.next()),
+                32 => Each,
+                33 => Over,
+                34 => Scan,
+                35 => EachPrior,
+                36 => EachLeft,
+                37 => EachRight,
+                38 => DivU,
+                39 => ModU,
+                40 => Lt,
+                41 => Gt,
+                42 => Eq,
+                43 => Match,
+                44 => And,
+                45 => Or,
+                46 => Not,
+                47 => Cat,
+                48 => Len,
+                49 => Take,
+                50 => Drop,
+                51 => Split,
+                52 => Join,
+                53 => Upper,
+                54 => Lower,
+                55 => Str,
+                56 => Parse,
+                57 => Dict,
+                58 => Get,
+                59 => Put,
+                60 => Del,
+                61 => Has,
+                62 => Keys,
+                63 => Values,
+                64 => Index,
+                65 => IndexDeep,
+                66 => Amend,
+                67 => Assign,
+                68 => Signal,
+                69 => Trap,
+                70 => Yield,
+                71 => Coro,
+                72 => Resume,
+                73 => Status,
+                _ => Push(Obj::F(1.)),
+            }
+        }
+    }
+
+    fn fuzz_n(n: usize) {
+        let mut r = Rng(0x9e3779b97f4a7c15);
+        for _ in 0..n {
+            let code = (0..r.below(24)).map(|_| r.instr()).collect::<Vec<_>>();
+            let bodies = (0..r.below(4) + 1)
+                .map(|_| body(r.below(code.len() + 2), r.size()))
+                .collect::<Vec<_>>();
+            let blocks = (0..r.below(5) + 1)
+                .map(|_| {
+                    let t = [
+                        BlkType::Fun,
+                        BlkType::Fun,
+                        BlkType::One,
+                        BlkType::Two,
+                    ];
+                    let d = [Time::Immediate, Time::Deferred];
+                    let b = r.below(bodies.len() + 1);
+                    Blk(t[r.below(4)], d[r.below(2)], b)
+                })
+                .collect::<Vec<_>>();
+
+            let mut vm = VM::new(Program::new(code, blocks, bodies));
+            for i in 0..vm.blocks().len() + 1 {
+                let _ = vm.exe_block(i);
+            }
+        }
+    }
+
+    #[test]
+    fn fuzz() {
+        fuzz_n(50000);
+    }
+
+    /** records every stop and answers with the next scripted step */
+    struct Script(Rc<RefCell<Vec<Stop>>>, Vec<Step>);
+
+    impl Debugger for Script {
+        fn stop(&mut self, _: &VM, s: &Stop, _: &mut Vec<Break>) -> Step {
+            self.0.borrow_mut().push(*s);
+            self.1.pop().unwrap_or(Step::Continue)
+        }
+    }
+
+    #[test]
+    fn debugger() {
+        let l = or_fatal(lisp::read("(@ (fn (x) (+ 1 x)) 2)"));
+        let mut m = Machine::new();
+        let b = or_fatal(m.compile(&l));
+        m.dump();
+
+        let run = |steps: Vec<Step>, breaks: &[Break], first: Step| {
+            let stops = Rc::new(RefCell::new(Vec::new()));
+            let mut vm = VM::new(m.program());
+            vm.debug(Script(stops.clone(), steps));
+            breaks.iter().for_each(|x| vm.break_at(*x));
+            vm.step(first);
+            let r = vm.exe_block(b).map(|x| vm.fmt(&x));
+            (r, stops.take())
+        };
+
+        /* single-stepping visits every instruction */
+        let (r, s) = run(vec![Step::Into; 64], &[], Step::Into);
+        assert_eq!(r, Ok("3".to_string()));
+        let all = s.iter().map(|x| x.at.instr).collect::<Vec<_>>();
+        assert_eq!(all, vec![7, 8, 9, 4, 5, 0, 1, 2]);
+        assert!(s.iter().all(|x| x.why == Why::Step));
+
+        /* stepping over the calls stays in the top body */
+        let (_, s) = run(vec![Step::Over; 64], &[], Step::Into);
+        let top = s.iter().map(|x| x.at.instr).collect::<Vec<_>>();
+        assert_eq!(top, vec![7, 8, 9]);
+
+        /* a block breakpoint stops once, on the block's first instr */
+        let f = m.blocks.iter().position(|x| x.1 == Time::Deferred);
+        let (_, s) = run(vec![], &[Break::Blk(f.unwrap())], Step::Continue);
+        assert_eq!(s.len(), 1);
+        assert_eq!((s[0].at.instr, s[0].why), (4, Why::Break));
+        assert_eq!(s[0].at.blk, f.unwrap());
+
+        /* stepping out of the lambda doesn't stop again, since every
+         * caller above it has nothing left after the call */
+        let (_, s) = run(vec![Step::Out], &[Break::Instr(5)], Step::Continue);
+        let out = s.iter().map(|x| x.at.instr).collect::<Vec<_>>();
+        assert_eq!(out, vec![5]);
+
+        let (r, _) = run(vec![Step::Quit], &[Break::Instr(0)], Step::Continue);
+        assert!(matches!(r, Err(Error::Halted { .. })));
+
+        /* a break instr stops without a breakpoint */
+        let code = vec![Instr::Push(Obj::U(1)), Instr::Break, Instr::Ret];
+        let blocks = vec![Blk(BlkType::Fun, Time::Immediate, 0)];
+        let stops = Rc::new(RefCell::new(Vec::new()));
+        let mut vm = VM::new(Program::new(code, blocks, vec![body(0, 0)]));
+        vm.debug(Script(stops.clone(), vec![]));
+        assert_eq!(vm.exe_block(0), Ok(Obj::U(1)));
+        let s = stops.take();
+        assert_eq!(s.len(), 1);
+        assert_eq!((s[0].at.instr, s[0].why), (1, Why::Break));
+    }
+
+    /** a writer the test can read back after the vm is done with it */
+    #[derive(Clone, Default)]
+    struct Buf(Rc<RefCell<Vec<u8>>>);
+
+    impl std::io::Write for Buf {
+        fn write(&mut self, x: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(x)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn tracer() {
+        let l = or_fatal(lisp::read("(@ (fn (x) [x (! 2)]) 2)"));
+        let mut m = Machine::new();
+        let b = or_fatal(m.compile(&l));
+
+        let buf = Buf::default();
+        let mut vm = VM::new(m.program());
+        vm.trace(Json::new(buf.clone()));
+        or_fatal(vm.exe_block(b));
+
+        let out = String::from_utf8(buf.0.take()).unwrap();
+        let count = |ev: &str| {
+            out.lines()
+                .filter(|x| x.starts_with(&format!(r#"{{"ev":"{ev}""#)))
+                .count()
+        };
+        assert_eq!(count("table"), 2);
+        assert_eq!(count("enter"), count("exit"));
+        assert_eq!(count("error"), 0);
+        assert!(out.lines().all(|x| x.starts_with('{') && x.ends_with('}')));
+
+        /* an error deep in a call is reported once */
+        let l = or_fatal(lisp::read("(@ (fn (x) (- 'a')) 2)"));
+        let mut m = Machine::new();
+        let b = or_fatal(m.compile(&l));
+
+        let buf = Buf::default();
+        let mut vm = VM::new(m.program());
+        vm.trace(Json::new(buf.clone()));
+        assert!(vm.exe_block(b).is_err());
+
+        let out = String::from_utf8(buf.0.take()).unwrap();
+        println!("{out}");
+        let e = out
+            .lines()
+            .filter(|x| x.contains(r#""ev":"error""#))
+            .collect::<Vec<_>>();
+        assert_eq!(e.len(), 1);
+        assert!(e[0].contains("expected F, got C"));
+    }
+
+    #[test]
+    fn profiler() {
+        let l = or_fatal(lisp::read("(@ (fn (x) (+ 1 x)) 2)"));
+        let mut m = Machine::new();
+        let b = or_fatal(m.compile(&l));
+
+        let p = Rc::new(RefCell::new(Profiler::new()));
+        let mut vm = VM::new(m.program());
+        vm.trace(p.clone());
+        or_fatal(vm.exe_block(b));
+        or_fatal(vm.exe_block(b));
+        vm.untrace();
+
+        let p = p.take();
+        println!("{}\n{}", p.report(&vm, 10), p.fold());
+        assert!(p.instrs.values().all(|x| x.count == 2));
+        assert!(p.bodies.values().all(|x| x.count == 2));
+        assert_eq!(p.blocks[&b].count, 2);
+        assert_eq!(p.hottest()[0].0, m.blocks[b].idx());
+
+        /* Apply1 and the lambda's Jmp each call one block */
+        assert_eq!(p.calls.len(), 2);
+        assert!(p.calls.values().all(|x| *x == 2));
+
+        let f = p.fold();
+        assert!(f.lines().all(|x| x.starts_with(&format!("blk{b}"))));
+        assert!(
+            f.lines()
+                .all(|x| x.rsplit(' ').next().unwrap().parse::<u128>().is_ok())
+        );
+    }
+
+    #[test]
+    fn coverage() {
+        let l = or_fatal(lisp::read("[(fn (x) (+ 1 x)) (! (+ 1 3))]"));
+        let mut m = Machine::new();
+        let b = or_fatal(m.compile(&l));
+
+        let c = Rc::new(RefCell::new(Coverage::new()));
+        let mut vm = VM::new(m.program());
+        vm.trace(c.clone());
+        or_fatal(vm.exe_block(b));
+
+        let c = c.take();
+        println!("{}", c.report(&vm));
+
+        /* the lambda is only ever a value, so its body never runs */
+        let f = m.blocks.iter().find(|x| x.1 == Time::Deferred).unwrap();
+        assert!(!c.bodies.contains(&f.idx()));
+        assert!(!c.instrs.contains(&m.bodies[f.idx()].start));
+
+        /* the iota loop was entered, went round and then fell out */
+        let jmp = |x: &Instr| matches!(x, Instr::LJmpNZ(_));
+        let i = m.instrs.iter().position(jmp).unwrap();
+        let j = m.instrs.iter().rposition(jmp).unwrap();
+        assert_eq!(c.branches[&i], (false, true));
+        assert_eq!(c.branches[&j], (true, true));
+        assert!(c.summary(&vm).contains("branches: 3/4"));
+        assert!(c.report(&vm).contains(&format!("!body {}", f.idx())));
+
+        /* an if taken both ways covers both of its arms */
+        let src = "(' (fn (x) (if (< x 2) `lo `hi)) [1 3])";
+        let l = or_fatal(lisp::read(src));
+        let mut m = Machine::new();
+        let b = or_fatal(m.compile(&l));
+        let c = Rc::new(RefCell::new(Coverage::new()));
+        let mut vm = VM::new(m.program());
+        vm.trace(c.clone());
+        let r = or_fatal(vm.exe_block(b));
+        assert_eq!(vm.fmt(&r), "[|`lo, `hi]");
+
+        let c = c.take();
+        let j = m.instrs.iter().position(|x| matches!(x, Instr::JmpZ(_)));
+        assert_eq!(c.branches[&j.unwrap()], (true, true));
+        assert!(c.summary(&vm).contains("branches: 2/2"));
+    }
+
+    #[test]
+    fn ifs() {
+        for (src, e) in [
+            ("(if 1 `a `b)", Ok("`a")),
+            ("(if 0 `a `b)", Ok("`b")),
+            ("(if 0.5 `a `b)", Ok("`a")),
+            /* only the arm it picks runs */
+            ("(if 1 2 (signal `no))", Ok("2")),
+            ("(if 0 (signal `no) 3)", Ok("3")),
+            ("(def x (if (< 1 2) (+ 1 2) 0)) [x (if x [x] nil)]",
+             Ok("[|3, [|3]]")),
+            ("(if `a 1 2)", Err(is!(Type { .. }))),
+        ] {
+            check(src, e);
+        }
+    }
+
+    #[test]
+    fn units() {
+        let math = or_fatal(lisp::read_all(
+            "(export inc (fn (x) (+ 1 x))) (def two 2) (export one 1)",
+        ));
+        let main = or_fatal(lisp::read_all("(import math inc) (@ inc 41)"));
+        let mut m = Machine::new();
+        let a = or_fatal(m.unit("math", &math));
+        let b = or_fatal(m.unit("main", &main));
+
+        let mut e = m.exports("math").unwrap();
+        e.sort_by(|x, y| x.0.cmp(&y.0));
+        assert_eq!(e, vec![
+            ("inc".into(), ObjType::Fun),
+            ("one".into(), ObjType::U)
+        ]);
+        for t in [LeafType::M, LeafType::D, LeafType::Fun] {
+            assert_eq!(ObjType::from(t), ObjType::Fun);
+        }
+
+        let mut vm = VM::new(m.program());
+        or_fatal(vm.exe_block(a));
+        let r = or_fatal(vm.exe_block(b));
+        assert_eq!(vm.fmt(&r), "42");
+
+        for (src, e) in [
+            ("(import math two)", is!(Private { .. })),
+            ("(import math three)", is!(Missing { .. })),
+            /* a unit's bindings can't be reached without an import,
+             * exported or not */
+            ("(+ two 1)", is!(Compile { .. })),
+            ("(@ inc 1)", is!(Compile { .. })),
+        ] {
+            let v = or_fatal(lisp::read_all(src));
+            let r = m.clone().unit("bad", &v);
+            assert!(r.as_ref().is_err_and(e), "{src}: {r:?}");
+        }
+    }
+
+    #[test]
+    fn verbs() {
+        let defs = "(def inc (fn (x) (+ 1 x))) (def add (fn (x y) (+ x y)))";
+        for (src, e) in [
+            ("(inc 41)", Ok("42")),
+            ("(add 40 2)", Ok("42")),
+            ("(@ inc (add 1 1))", Ok("3")),
+            ("[(inc 1) (add 2 (inc 2))]", Ok("[|2, 5]")),
+            ("(@ (@ add 1) 41)", Ok("42")),
+            ("(inc 1 2)", Err((1, 2))),
+        ] {
+            let src = format!("{defs} {src}");
+            let v = or_fatal(lisp::read_all(&src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.unit("main", &v));
+            let t = m.blocks.iter().filter(|x| x.1 == Time::Deferred);
+            assert_eq!(
+                t.map(|x| x.0).collect::<Vec<_>>(),
+                vec![BlkType::One, BlkType::Two]
+            );
+
+            let mut vm = VM::new(m.program());
+            match (vm.exe_block(b), e) {
+                (Ok(r), Ok(e)) => assert_eq!(vm.fmt(&r), e, "{src}"),
+                (Err(Error::Arity { expected, actual, .. }), Err(e)) => {
+                    assert_eq!((expected, actual), e, "{src}")
+                }
+                (r, _) => panic!("{src}: {r:?}"),
+            }
+        }
+
+        /* a verb with nothing to work on is a value, like a function.
+         * with too little, it's a projection */
+        let blocks = [Blk(BlkType::Two, Time::Deferred, 0)];
+        let bodies = [body(0, 2)];
+        let code = [Instr::Local(0), Instr::Local(1), Instr::Ret];
+        let mut vm = VM::new(Program::new(code, blocks, bodies));
+        assert_eq!(vm.exe_block(0), Ok(Obj::Fun(0)));
+        vm.stack.push(Obj::F(1.));
+        let p = or_fatal(vm.exe_block(0));
+        assert_eq!(vm.fmt(&p), "{&0}[1;]");
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn projections() {
+        let defs = "(def pair (fn (x y) [x y])) (def add (fn (x y) (+ x y)))";
+        for (src, e) in [
+            ("(@ (pair 1 _) 2)", Ok("[|1, 2]")),
+            ("(@ (pair _ 1) 2)", Ok("[|2, 1]")),
+            ("(@ (@ (add _ _) 1) 2)", Ok("3")),
+            ("(def inc (add _ 1)) (inc 41)", Ok("42")),
+            (
+                "(def p (. (fn (x y z) [x y z]) [1 2])) (. p [3])",
+                Ok("[|1, 2, 3]"),
+            ),
+            ("(@ (. (fn (x y z) [x y z]) [1 2]) 3)", Ok("[|1, 2, 3]")),
+            (
+                "(. (pair _ _) [1 2 3])",
+                Err(is!(Arity { expected: 2, actual: 3, .. })),
+            ),
+            (
+                "(. (pair 1 _) [2 3])",
+                Err(is!(Arity { expected: 1, actual: 2, .. })),
+            ),
+            /* a call's args are its own, not whatever's under it */
+            ("[7 (. (fn (x y) (+ x y)) [1 2])]", Ok("[|7, 3]")),
+            ("(def v [1 2]) [7 (. add v) (. pair v)]", Ok("[|7, 3, [|1, 2]]")),
+            ("(@ pair[1;] 2)", Ok("[|1, 2]")),
+            ("(@ pair[;1] 2)", Ok("[|2, 1]")),
+            ("(@ pair[1 _] 2)", Ok("[|1, 2]")),
+            ("(def f (fn (x y) [x y])) (@ f[1;] 2)", Ok("[|1, 2]")),
+            ("(def g pair[1;]) g[2]", Ok("[|1, 2]")),
+            ("(. pair[;] [1 2])", Ok("[|1, 2]")),
+        ] {
+            check(&format!("{defs} {src}"), e);
+        }
+    }
+
+    #[test]
+    fn adverbs() {
+        let defs = "(def inc (fn (x) (+ 1 x))) (def pair (fn (x y) [x y]))";
+        for (src, e) in [
+            (r"(' inc [1 2 3])", "[|2, 3, 4]"),
+            (r"(' (+ 10 _) [1 2])", "[|11, 12]"),
+            (r"(/ + [1 2 3 4])", "10"),
+            (r"(/ (fn (x y) (+ x y)) (! 4))", "6"),
+            (r"(/ - [])", "[|]"),
+            (r"(\ + [1 2 3])", "[|1, 3, 6]"),
+            (r"(': - [1 3 6])", "[|1, 2, 3]"),
+            (r"(\: pair [1 2] 3)", "[|[|1, 3], [|2, 3]]"),
+            (r"(/: pair 0 [1 2])", "[|[|0, 1], [|0, 2]]"),
+            (r"(/ pair (' inc [0 1 2]))", "[|[|1, 2], 3]"),
+            /* surplus args are an error, not left under the call */
+            (r"[9 (' (fn (x) 7) [1 2])]", "[|9, [|7, 7]]"),
+            (
+                r"[9 (trap (fn (x) (' (fn () 7) x)) [[1 2]] (fn (e) e))]",
+                r#"[|9, "arity"]"#,
+            ),
+        ] {
+            let src = format!("{defs} {src}");
+            let v = or_fatal(lisp::read_all(&src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.unit("main", &v));
+            let mut vm = VM::new(m.program());
+            let r = or_fatal(vm.exe_block(b));
+            assert_eq!(vm.fmt(&r), e, "{src}");
+            assert!(vm.stack.is_empty(), "{src}: {:?}", vm.stack);
+        }
+
+        let v = or_fatal(lisp::read_all("(' (fn (x) x) 1)"));
+        let mut m = Machine::new();
+        let b = or_fatal(m.unit("main", &v));
+        let mut vm = VM::new(m.program());
+        assert!(matches!(vm.exe_block(b), Err(Error::Type { .. })));
+    }
+
+    #[test]
+    fn atomic() {
+        use ObjType::{C, F};
+        for (src, e) in [
+            ("(+ 1 [1 2 3])", Ok("[|2, 3, 4]")),
+            ("(* [1 2 3] 2)", Ok("[|2, 4, 6]")),
+            ("(- [5 5] [1 2])", Ok("[|4, 3]")),
+            ("(% (! 3) 2)", Ok("[|0, 0.5, 1]")),
+            ("(+ [1 [2 3]] 10)", Ok("[|11, [|12, 13]]")),
+            ("(+ [1 [2 3]] [1 [1 1]])", Ok("[|2, [|3, 4]]")),
+            ("(- [1 [2 3]])", Ok("[|-1, [|-2, -3]]")),
+            (
+                "(+ [1 2] [1 2 3])",
+                Err(is!(Length { expected: 2, actual: 3, .. })),
+            ),
+            (
+                "(+ [1 [2 3]] [1 [1]])",
+                Err(is!(Length { expected: 2, actual: 1, .. })),
+            ),
+            ("(+ [1 2] 'a')", Err(is!(Type { expected: F, actual: C, .. }))),
+        ] {
+            check(src, e);
+        }
+    }
+
+    #[test]
+    fn integers() {
+        use ObjType::{F, U};
+        for (src, e, t) in [
+            ("(+ 1 2)", "3", U),
+            ("(* 6 7)", "42", U),
+            ("(- 1 2)", "-1", F),
+            ("(- 3)", "-3", F),
+            ("(+ 1 2.5)", "3.5", F),
+            ("(% 7 2)", "3.5", F),
+            ("(div 7 2)", "3", U),
+            ("(mod 7 2)", "1", U),
+            ("(div 7 0)", "inf", F),
+            ("(div -7 2)", "-4", F),
+            ("(mod -7 2)", "1", F),
+            ("(* 4294967296 4294967296)", "18446744073709552000", F),
+            /* usize::MAX is 0N, so reaching it overflows */
+            ("(+ 18446744073709551613 1)", "18446744073709551614", U),
+            ("(+ 18446744073709551614 1)", "18446744073709552000", F),
+            ("(* 3 6148914691236517205)", "18446744073709552000", F),
+            ("(num \"18446744073709551615\")", "18446744073709552000", F),
+            ("18446744073709551615", "18446744073709552000", F),
+            ("(! 3)", "[|0, 1, 2]", U),
+            ("(! (+ 1 2))", "[|0, 1, 2]", U),
+            /* the bound is tested before the first step */
+            ("(! (+ 0 0))", "[|]", U),
+            ("(! (+ 0 1))", "[|0]", U),
+            ("(! (- 1 3))", "[|]", U),
+            ("(- 0)", "0", F),
+            ("(- (+ 0 0))", "0", F),
+            ("(+ (! 3) 1)", "[|1, 2, 3]", U),
+            ("(+ (! 3) 0.5)", "[|0.5, 1.5, 2.5]", F),
+        ] {
+            let l = or_fatal(lisp::read(src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.compile(&l));
+            let mut vm = VM::new(m.program());
+            let r = or_fatal(vm.exe_block(b));
+            assert_eq!(vm.fmt(&r), e, "{src}");
+
+            let v = vm.items(r).unwrap_or(vec![r]);
+            assert!(v.iter().all(|x| x.ty() == t), "{src}: {v:?}");
+        }
+    }
+
+    #[test]
+    fn logic() {
+        use ObjType::{C, F, Fun};
+        for (src, e) in [
+            ("(< 1 2)", Ok("1")),
+            ("(> 1 2)", Ok("0")),
+            ("(= 1 1.0)", Ok("1")),
+            ("(< 'a' 'b')", Ok("1")),
+            ("(= 'a' 1)", Ok("0")),
+            ("(< (! 5) 2)", Ok("[|1, 1, 0, 0, 0]")),
+            ("(= [1 2 3] [1 5 3])", Ok("[|1, 0, 1]")),
+            ("(~ [1 [2 3]] [1 [2 3]])", Ok("1")),
+            ("(~ [1 2] [1 2 3])", Ok("0")),
+            ("(~ 1 1.0)", Ok("0")),
+            ("(& 1 0)", Ok("0")),
+            ("(| 1 0)", Ok("1")),
+            ("(not [0 1 2])", Ok("[|1, 0, 0]")),
+            ("(& (> (! 5) 0) (< (! 5) 3))", Ok("[|0, 1, 1, 0, 0]")),
+            ("(/ + (> (! 5) 2))", Ok("2")),
+            ("(< 'a' 1)", Err(is!(Type { expected: F, actual: C, .. }))),
+            ("(| 1 'a')", Err(is!(Type { expected: F, actual: C, .. }))),
+            (
+                "(not (fn (x) x))",
+                Err(is!(Type { expected: F, actual: Fun, .. })),
+            ),
+            (
+                "(= [1 2] [1 2 3])",
+                Err(is!(Length { expected: 2, actual: 3, .. })),
+            ),
+        ] {
+            check(src, e);
+        }
+    }
+
+    #[test]
+    fn strings() {
+        use ObjType::{C, U};
+        for (src, e) in [
+            (r#""hello""#, Ok(r#""hello""#)),
+            (r#""say \"hi\"\n""#, Ok(r#""say \"hi\"\n""#)),
+            (r#"(, "foo" "bar")"#, Ok(r#""foobar""#)),
+            (r#"(, "ab" 'c')"#, Ok(r#""abc""#)),
+            (r#"(, 1 [2 3])"#, Ok("[|1, 2, 3]")),
+            (r#"(# "hello")"#, Ok("5")),
+            (r#"(# 7)"#, Ok("1")),
+            (r#"(# 3 "hello")"#, Ok(r#""hel""#)),
+            (r#"(# 2 (drop 1 "hello"))"#, Ok(r#""el""#)),
+            (r#"(drop 9 "hello")"#, Ok("[|]")),
+            (r#"(split ',' "a,b,,c")"#, Ok(r#"[|"a", "b", [|], "c"]"#)),
+            (r#"(split ", " "a, b")"#, Ok(r#"[|"a", "b"]"#)),
+            (r#"(join "--" (split ',' "a,b,c"))"#, Ok(r#""a--b--c""#)),
+            (r#"(upper "MiXed 1")"#, Ok(r#""MIXED 1""#)),
+            (r#"(upper 1)"#, Err(is!(Type { expected: C, actual: U, .. }))),
+            (r#"(lower "MiXed")"#, Ok(r#""mixed""#)),
+            (r#"($ 42)"#, Ok(r#""42""#)),
+            (r#"($ (% 1 4))"#, Ok(r#""0.25""#)),
+            (r#"($ [1 2])"#, Ok(r#"[|"1", "2"]"#)),
+            (r#"($ "s")"#, Ok(r#""s""#)),
+            (r#"(num "42")"#, Ok("42")),
+            (r#"(+ 1 (num (split ',' "1,2.5")))"#, Ok("[|2, 3.5]")),
+            (r#"(num "4x")"#, Err(is!(Parse { text, .. } if text == "4x"))),
+            (r#"(= "abc" "abd")"#, Ok("[|1, 1, 0]")),
+            (r#"(~ "abc" "abc")"#, Ok("1")),
+        ] {
+            check(src, e);
+        }
+    }
+
+    #[test]
+    fn symbols() {
+        for (src, e) in [
+            ("`abc", "`abc"),
+            ("[`a `b]", "[|`a, `b]"),
+            ("(= `a `a)", "1"),
+            ("(= `a `b)", "0"),
+            ("(< `a `b)", "1"),
+            ("(= [`a `b] `b)", "[|0, 1]"),
+            ("(~ [`a `b] [`a `b])", "1"),
+            ("($ `abc)", r#""abc""#),
+        ] {
+            let l = or_fatal(lisp::read(src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.compile(&l));
+            let mut vm = VM::new(m.program());
+            let r = or_fatal(vm.exe_block(b));
+            assert_eq!(vm.fmt(&r), e, "{src}");
+        }
+
+        /* the same name is the same symbol in a program's table */
+        let mut syms = Syms::default();
+        let (a, b) = (syms.intern("key"), syms.intern("other"));
+        assert_eq!(syms.intern("key"), a);
+        assert_eq!(syms.name(b), "other");
+        assert_eq!(syms.get("neither"), None);
+
+        use Instr::*;
+        let code = [
+            Push(Obj::S(a)),
+            Push(Obj::U(1)),
+            Push(Obj::S(b)),
+            Push(Obj::U(2)),
+            Table(2),
+            Ret,
+        ];
+        let blocks = [Blk(BlkType::Fun, Time::Immediate, 0)];
+        let bodies = [body(0, 0)];
+        let mut p = Program::new(code, blocks, bodies);
+        p.grow().syms = syms;
+        let mut vm = VM::new(p);
+        let Ok(Obj::T(t)) = vm.exe_block(0) else {
+            panic!("no table");
+        };
+        assert_eq!(vm.fmt(&Obj::T(t)), "[`key: 1, `other: 2|]");
+        let t = or_fatal(vm.get_table(t));
+        assert_eq!(t.get(Obj::S(b)), Some(Obj::U(2)));
+
+        /* each machine has its own table, and linking maps one into
+         * the other */
+        let mut m = Machine::new();
+        let x = or_fatal(m.compile(&or_fatal(lisp::read("`x"))));
+        let mut n = Machine::new();
+        let y = or_fatal(n.compile(&or_fatal(lisp::read("`y"))));
+        assert_eq!(m.syms.get("x"), n.syms.get("y"));
+        let l = or_fatal(link(&[&m, &n]));
+        let y = y + m.blocks.len();
+        let mut vm = VM::new(l.program());
+        let r = [x, y].map(|b| or_fatal(vm.exe_block(b)));
+        assert_eq!(r.map(|x| vm.fmt(&x)), ["`x", "`y"]);
+    }
+
+    #[test]
+    fn dicts() {
+        use ObjType::{T, U};
+        for (src, e) in [
+            ("(! [`a `b] [1 2])", Ok("[`a: 1, `b: 2|]")),
+            ("(! `a 1)", Ok("[`a: 1|]")),
+            ("(! [`a `a] [1 2])", Ok("[`a: 2|]")),
+            (
+                "(! [1 2] [3])",
+                Err(is!(Length { expected: 2, actual: 1, .. })),
+            ),
+            ("(get (! [`a `b] [1 2]) `b 0)", Ok("2")),
+            ("(get (! [`a `b] [1 2]) `c 0)", Ok("0")),
+            ("(get 1 `c 0)", Err(is!(Type { expected: T, actual: U, .. }))),
+            ("(get (put (! [`a] [1]) `b 2) `b 0)", Ok("2")),
+            ("(put (! [`a `b] [1 2]) `a 3)", Ok("[`a: 3, `b: 2|]")),
+            ("(keys (del (! [`a `b `c] [1 2 3]) `b))", Ok("[|`a, `c]")),
+            ("(get (del (! [`a `b `c] [1 2 3]) `a) `c 0)", Ok("3")),
+            ("(values (! [`a `b] [1 2]))", Ok("[|1, 2]")),
+            ("(has (! [`a] [1]) `a)", Ok("1")),
+            ("(has (! [`a] [1]) `b)", Ok("0")),
+            /* strings are tables, which are keyed by identity */
+            (r#"(get (! ["ab"] [1]) "ab" 0)"#, Ok("0")),
+            /* keys have to be the same type to be the same key */
+            ("(get (! [1] [2]) 1.0 9)", Ok("9")),
+            ("(get (! [(% 0 0)] [1]) (% 0 0) 9)", Ok("1")),
+            ("(get (! [0.0] [1]) (- 0.0) 9)", Ok("1")),
+        ] {
+            check(src, e);
+        }
+    }
+
+    #[test]
+    fn index() {
+        use ObjType::U;
+        for (src, e) in [
+            ("(def v [10 20 30]) v[1]", Ok("20")),
+            ("(def v [10 20 30]) (@ v 2)", Ok("30")),
+            ("(def v [10 20 30]) (at v [2 0])", Ok("[|30, 10]")),
+            (
+                "(def v [10 20 30]) (at v [[0 1] [2]])",
+                Ok("[|[|10, 20], [|30]]"),
+            ),
+            ("(def v [10 20 30]) v[3]", Ok("0N")),
+            ("(def v [10 20 30]) v[`a]", Ok("0N")),
+            ("(def m [[1 2] [3 4]]) m[1 0]", Ok("3")),
+            ("(def m [[1 2] [3 4]]) m[1][0]", Ok("3")),
+            ("(def m [[1 2] [3 4]]) m[[0 1] 1]", Ok("[|2, 4]")),
+            ("(def m [[1 2] [3 4]]) (dot m [1 1])", Ok("4")),
+            ("(def d (! [`a `b] [1 2])) d[`b]", Ok("2")),
+            ("(def d (! [`a `b] [1 2])) d[[`b `a]]", Ok("[|2, 1]")),
+            ("(def d (! [`a `b] [1 2])) d[`c]", Ok("0N")),
+            ("(def d (! [1 2] [`a `b])) d[2]", Ok("`b")),
+            ("(def f (fn (x y) (- x y))) f[5 2]", Ok("3")),
+            (
+                "(def v [1 2 3]) (amend v 1 (fn (x) (* x 10)))",
+                Ok("[|1, 20, 3]"),
+            ),
+            ("(def v [1 2 3]) (amend v [0 2] (fn (x) 0)) v", Ok("[|1, 2, 3]")),
+            (
+                "(def v [1 2 3]) (amend v 5 (fn (x) 0))",
+                Err(is!(Index { index: 5, .. })),
+            ),
+            ("(def v [1 2 3]) (assign v 0 9) v", Ok("[|9, 2, 3]")),
+            ("(def v [1 2 3]) (assign v [0 1] 7)", Ok("[|7, 7, 3]")),
+            ("(def v [1 2 3]) (assign v [0 1] [8 9])", Ok("[|8, 9, 3]")),
+            ("(def v [1 2 3]) (assign v [0 1] [8])", Err(is!(Length { .. }))),
+            (
+                "(def v [1 2 3]) (assign v `a 1)",
+                Err(is!(Type { expected: U, .. })),
+            ),
+            ("(def d (! [`a] [1])) (assign d `b 2)", Ok("[`a: 1, `b: 2|]")),
+            (
+                "(def d (! [`a] [1])) (amend d `a (fn (x) (+ x 1)))",
+                Ok("[`a: 2|]"),
+            ),
+            /* a table can't be put inside itself, however deep */
+            (
+                "(def v [1 2]) (assign v 0 v) (~ v v)",
+                Err(is!(Cycle { .. })),
+            ),
+            (
+                "(def v [1 2]) (def w [0 v]) (assign v 0 w)",
+                Err(is!(Cycle { .. })),
+            ),
+            (
+                "(def f (fn (x y) x)) (def v [1 2]) (assign v 0 (f v _))",
+                Err(is!(Cycle { .. })),
+            ),
+            ("(def d (! [`a] [1])) (put d `b d)", Err(is!(Cycle { .. }))),
+            ("(def d (! [`a] [1])) (put d d 1)", Err(is!(Cycle { .. }))),
+            /* amend works on a copy, so the original can go in it */
+            ("(def v [1 2]) (amend v 0 (fn (x) v))", Ok("[|[|1, 2], 2]")),
+        ] {
+            check(src, e);
+        }
+
+        /* tables nested past the depth limit are too deep to walk */
+        let deep = "(def x (/ (fn (x y) [x]) (! 300)))";
+        for src in ["(~ x x)", "($ x)", "(num x)", "(+ x 1)", "(- x)"] {
+            check(&format!("{deep} {src}"), Err(is!(Overflow { .. })));
+        }
+        let n = MAX_DEPTH;
+        let e = format!("{}...{}", "[|".repeat(n), "]".repeat(n));
+        check(&format!("{deep} x"), Ok(e.leak()));
+
+        /* as are builtins calling back into bytecode, which nest on the
+         * rust stack and not just in frames */
+        let each = (0..8).fold("1".to_string(), |x, _| {
+            format!("(at (' (fn (x) {x}) [1]) 0)")
+        });
+        check(&each, Ok("1"));
+        let f = "(def f (fn (g) (at (' g [g]) 0)))";
+        check(&format!("{f} (@ f f)"), Err(is!(Overflow { .. })));
+
+        /* and source nested too deeply to read or compile */
+        let n = lisp::MAX_NEST + 1;
+        let src = format!("{}1{}", "[".repeat(n), "]".repeat(n));
+        let r = lisp::read_all(&src);
+        assert!(matches!(r, Err(Error::Compile { .. })), "{r:?}");
+        let l = (0..n).fold(Leaf::U(1), |x, _| Leaf::A(vec![x]));
+        let r = Machine::new().compile(&l);
+        assert!(matches!(r, Err(Error::Overflow { .. })), "{r:?}");
+    }
+
+    #[test]
+    fn nulls() {
+        use ObjType::{F, N};
+        for (src, e) in [
+            ("nil", Ok("nil")),
+            ("[0N 0n ' ']", Ok("[|0N, 0n,  ]")),
+            ("(def v [1.5 2.5]) v[9]", Ok("0n")),
+            ("(def v \"ab\") (= v[9] ' ')", Ok("1")),
+            ("(def v [nil 1]) v[9]", Ok("nil")),
+            ("(def v []) v[0]", Ok("nil")),
+            ("(+ 0N 1)", Ok("0N")),
+            ("(* 2 [1 0N])", Ok("[|2, 0N]")),
+            ("(+ 0N 1.5)", Ok("0n")),
+            ("(% 0N 2)", Ok("0n")),
+            ("(+ 0n 1)", Ok("0n")),
+            ("(- 0N)", Ok("0n")),
+            ("(+ nil 1)", Err(is!(Type { expected: F, actual: N, .. }))),
+            ("(= 0N 0N)", Ok("1")),
+            ("(= 0N 0n)", Ok("1")),
+            ("(= 0n 1)", Ok("0")),
+            ("(= nil nil)", Ok("1")),
+            ("(= nil 0)", Ok("0")),
+            ("(~ 0n 0n)", Ok("1")),
+            ("(~ 0N 0n)", Ok("0")),
+            ("(< 0N 0)", Ok("1")),
+            ("(< 0n 0N)", Ok("0")),
+            ("(> 1 0n)", Ok("1")),
+            ("(< nil 1)", Err(is!(Type { .. }))),
+            ("($ 0N)", Ok("\"0N\"")),
+            ("(num \"0n\")", Ok("0n")),
+            /* the biggest integers are numbers, not 0N */
+            ("(= 18446744073709551614 0N)", Ok("0")),
+            ("(= (+ 18446744073709551614 1) 0N)", Ok("0")),
+            ("(< (+ 18446744073709551614 1) 0)", Ok("0")),
+            ("(< 18446744073709551614 0N)", Ok("0")),
+            ("(< 0N 18446744073709551614)", Ok("1")),
+            ("(- (+ 18446744073709551614 1))", Ok("-18446744073709552000")),
+        ] {
+            check(src, e);
+        }
+
+        assert!(Obj::NU.is_null() && !Obj::U(usize::MAX - 1).is_null());
+
+        /* a body that leaves nothing on the stack returns nil */
+        let code = [Instr::Push(Obj::U(1)), Instr::Pop, Instr::Ret];
+        let blocks = [Blk(BlkType::Fun, Time::Immediate, 0)];
+        let bodies = [body(0, 0)];
+        let mut vm = VM::new(Program::new(code, blocks, bodies));
+        assert_eq!(vm.exe_block(0), Ok(Obj::N));
+    }
+
+    #[test]
+    fn traps() {
+        for (src, e) in [
+            ("(trap (fn (x) (+ x 1)) [1] (fn (e) e))", Ok("2")),
+            ("(trap (fn (x) (signal `oops)) [1] (fn (e) e))", Ok("`oops")),
+            ("(trap (fn (x) (+ x 'a')) [1] (fn (e) e))", Ok(r#""type""#)),
+            (
+                "(trap (fn (x) (+ x [1 2])) [[1]] (fn (e) e))",
+                Ok(r#""length""#),
+            ),
+            ("(trap (fn (x y) x) [1 2 3] (fn (e) e))", Ok(r#""arity""#)),
+            /* a call with no args still runs the function */
+            ("(trap (fn () 7) [] (fn (e) e))", Ok("7")),
+            ("[1 (trap (fn () 7) [] (fn (e) e))]", Ok("[|1, 7]")),
+            /* the stack under a trap survives what happens inside it */
+            (
+                "[7 (trap (fn (x) (signal x)) [5] (fn (e) (* e 2)))]",
+                Ok("[|7, 10]"),
+            ),
+            (
+                "(trap (fn (x) (trap (fn (y) (signal y)) [x] \
+                   (fn (e) (signal (+ e 1))))) [1] (fn (e) e))",
+                Ok("2"),
+            ),
+            ("(signal `oops)", Err(is!(Signal { value: Obj::S(_), .. }))),
+            (
+                "(trap (fn (x) (signal x)) [1] (fn (e) (signal `no)))",
+                Err(is!(Signal { .. })),
+            ),
+        ] {
+            let vm = check(src, e);
+            assert!(vm.stack.is_empty(), "{src}: {:?}", vm.stack);
+        }
+    }
+
+    #[test]
+    fn limits() {
+        let run = |src: &str, limits: Limits| {
+            let l = or_fatal(lisp::read(src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.compile(&l));
+            let mut vm = VM::new(m.program());
+            vm.limit(limits);
+            let r = vm.exe_block(b).map(|x| vm.fmt(&x));
+            (r, vm.ran())
+        };
+        let limit = |e: &Result<String, Error>| match e {
+            Err(Error::Limit { limit, .. }) => Some(*limit),
+            _ => None,
+        };
+
+        let (r, n) = run("(! 100)", Limits::default());
+        assert!(r.is_ok());
+        let (r, m) = run("(! 100)", Limits {
+            fuel: Some(n - 1),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&r), Some(Limit::Fuel));
+        assert_eq!(m, n - 1);
+        let (r, _) = run("(! 100)", Limits {
+            fuel: Some(n),
+            ..Limits::default()
+        });
+        assert!(r.is_ok());
+
+        let (r, _) = run("[(! 3) (! 3)]", Limits {
+            tables: Some(2),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&r), Some(Limit::Tables));
+        let (r, _) = run("(, (! 3) (! 3))", Limits {
+            elems: Some(5),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&r), Some(Limit::Elems));
+        /* the two halves are garbage once they're joined */
+        let (r, _) = run("(, (! 3) (! 3))", Limits {
+            elems: Some(8),
+            ..Limits::default()
+        });
+        assert_eq!(r, Ok("[|0, 1, 2, 0, 1, 2]".to_string()));
+
+        /* loops that drop what they make get it collected, while what
+         * a builtin is holding on to stays */
+        let (r, _) = run("(/ (fn (a y) (# [y y])) (! 50))", Limits {
+            tables: Some(3),
+            ..Limits::default()
+        });
+        assert_eq!(r, Ok("2".to_string()));
+        let each = "(' (fn (y) (@ (fn (z) [y]) (! 3))) (! 10))";
+        let (r, _) = run(each, Limits {
+            tables: Some(12),
+            ..Limits::default()
+        });
+        let e = (0..10).map(|x| format!("[|{x}]")).collect::<Vec<_>>();
+        assert_eq!(r, Ok(format!("[|{}]", e.join(", "))));
+        let (r, _) = run(each, Limits {
+            tables: Some(11),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&r), Some(Limit::Tables));
+        let amend = "(amend (! 3) [0 1] (fn (x) (# [x x])))";
+        let (r, _) = run(amend, Limits {
+            tables: Some(4),
+            ..Limits::default()
+        });
+        assert_eq!(r, Ok("[|2, 2, 2]".to_string()));
+        /* a trap sets the caller's stack aside, but it's still live */
+        let trap = "[(! 3) (trap (fn (x) (/ (fn (a y) (# [y y])) x)) \
+                    [(! 20)] (fn (e) e)) (! 2)]";
+        let (r, _) = run(trap, Limits {
+            tables: Some(5),
+            ..Limits::default()
+        });
+        assert_eq!(r, Ok("[|[|0, 1, 2], 2, [|0, 1]]".to_string()));
+
+        /* projections and coroutines nothing holds get collected too */
+        let objs = "(/ (fn (a y) (+ (@ (. (fn (x z) (+ a x)) [y]) 1) \
+                    (resume (coro (fn (x) x)) y))) (! 50))";
+        let (r, _) = run(objs, Limits {
+            objs: Some(2),
+            ..Limits::default()
+        });
+        assert_eq!(r, Ok("2450".to_string()));
+        let (r, _) = run("[(. (fn (x z) x) [1]) (coro (fn (x) x))]", Limits {
+            objs: Some(1),
+            ..Limits::default()
+        });
+        assert_eq!(limit(&r), Some(Limit::Objs));
+
+        /* and so does what a host drops between runs */
+        let l = or_fatal(lisp::read("(! 3)"));
+        let mut m = Machine::new();
+        let b = or_fatal(m.compile(&l));
+        let mut vm = VM::new(m.program());
+        vm.limit(Limits {
+            tables: Some(1),
+            ..Limits::default()
+        });
+        for _ in 0..10 {
+            let r = or_fatal(vm.exe_block(b));
+            assert_eq!(vm.fmt(&r), "[|0, 1, 2]");
+        }
+
+        let nest = "(@ (fn (x) (@ (fn (y) (@ (fn (z) z) y)) x)) 1)";
+        let (r, _) = run(nest, Limits {
+            depth: 4,
+            ..Limits::default()
+        });
+        assert!(matches!(r, Err(Error::Overflow { .. })), "{r:?}");
+
+        /* a trap catches them like any other error */
+        let trap = format!("(trap (fn (x) {nest}) [0] (fn (e) e))");
+        let (r, _) = run(&trap, Limits {
+            depth: 6,
+            ..Limits::default()
+        });
+        assert_eq!(r, Ok(r#""overflow""#.to_string()));
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let c = cancel.clone();
+        or_fatal(
+            thread::spawn(move || c.store(true, Ordering::Relaxed))
+                .join()
+                .map_err(|_| "cancelling thread panicked"),
+        );
+        let (r, n) = run("(! 3)", Limits {
+            cancel: Some(cancel),
+            ..Limits::default()
+        });
+        assert!(matches!(r, Err(Error::Cancelled { .. })), "{r:?}");
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn yields() {
+        let unit = |src: &str| {
+            let v = or_fatal(lisp::read_all(src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.unit("main", &v));
+            (m, b)
+        };
+
+        let (m, b) =
+            unit("(def a (yield 1)) (def b (yield (+ a 1))) (+ a b)");
+        let mut vm = VM::new(m.program());
+        assert!(!vm.suspended());
+        assert_eq!(or_fatal(vm.start(b)), Run::Yielded(Obj::U(1)));
+        assert!(vm.suspended());
+        assert!(matches!(vm.start(b), Err(Error::Resume { .. })));
+
+        /* a clone carries on from the same place, on its own */
+        let mut c = vm.clone();
+        assert_eq!(c, vm);
+        assert!(format!("{c:?}").starts_with("VM {"));
+        assert_eq!(or_fatal(c.resume(Obj::U(1))), Run::Yielded(Obj::U(2)));
+        assert_ne!(c, vm);
+
+        assert_eq!(or_fatal(vm.resume(Obj::U(10))), Run::Yielded(Obj::U(11)));
+        assert_eq!(or_fatal(vm.resume(Obj::U(5))), Run::Finished(Obj::U(15)));
+        assert_eq!(or_fatal(c.resume(Obj::U(2))), Run::Finished(Obj::U(3)));
+        assert!(!vm.suspended());
+        assert!(vm.frames().is_empty());
+        assert!(matches!(vm.resume(Obj::N), Err(Error::Resume { .. })));
+
+        /* a yield inside a function suspends the whole call chain */
+        let (m, b) = unit("(def f (fn (x) (* 2 (yield x)))) (+ 1 (@ f 3))");
+        let mut vm = VM::new(m.program());
+        assert_eq!(or_fatal(vm.start(b)), Run::Yielded(Obj::U(3)));
+        assert!(vm.depth() > 1);
+        assert_eq!(or_fatal(vm.resume(Obj::U(4))), Run::Finished(Obj::U(9)));
+
+        /* but not out from under a builtin calling back into bytecode */
+        for src in ["(' (fn (x) (yield x)) [1 2])", "(yield 1)"] {
+            let (m, b) = unit(src);
+            let mut vm = VM::new(m.program());
+            let r = vm.exe_block(b);
+            assert!(matches!(r, Err(Error::Yield { .. })), "{src}: {r:?}");
+        }
+        let (m, b) = unit("(' (fn (x) (yield x)) [1 2])");
+        let mut vm = VM::new(m.program());
+        assert!(matches!(vm.start(b), Err(Error::Yield { .. })));
+        assert!(!vm.suspended());
+    }
+
+    #[test]
+    fn coros() {
+        let defs = "(def gen (fn (x) \
+                      (at [(yield x) (yield (+ x 1)) (+ x 2)] 2))) \
+                    (def acc (fn (x) (+ x (yield x))))";
+        for (src, e) in [
+            ("(def c (coro gen)) [(resume c 1) (resume c 0) (resume c 0)]",
+             Ok("[|1, 2, 3]")),
+            ("(def c (coro gen)) \
+              [(status c) (resume c 1) (status c) (resume c 0) \
+               (resume c 0) (status c)]",
+             Ok("[|`ready, 1, `suspended, 2, 3, `dead]")),
+            /* a resume's value is what the yield gives */
+            ("(def c (coro acc)) [(resume c 10) (resume c 5)]",
+             Ok("[|10, 15]")),
+            /* coroutines keep their own stacks */
+            ("(def c (coro acc)) (def d (coro acc)) \
+              [(resume c 1) (resume d 10) (resume c 5) (resume d 5)]",
+             Ok("[|1, 10, 6, 15]")),
+            /* and their own locals, though they run the same function */
+            ("(def c (coro gen)) (def d (coro gen)) \
+              [(resume c 1) (resume d 10) (resume c 0) (resume c 0) \
+               (resume d 0)]",
+             Ok("[|1, 10, 2, 3, 11]")),
+            /* yields go to the nearest resume, even under a builtin */
+            ("(def c (coro gen)) (' (fn (x) (resume c x)) [5 0 0])",
+             Ok("[|5, 6, 7]")),
+            ("(def c (coro (fn (x) (+ 1 (resume (coro gen) x))))) \
+              (resume c 4)",
+             Ok("5")),
+            ("(def c (coro (fn () 7))) [(resume c nil) (status c)]",
+             Ok("[|7, `dead]")),
+            ("(def c (coro gen)) (resume c 1) (resume c 0) (resume c 0) \
+              (resume c 0)",
+             Err("resume")),
+            ("(def c (coro (fn (x) (resume x 0)))) (resume c c)",
+             Err("resume")),
+            ("(coro 1)", Err("type")),
+            /* errors kill the coroutine and reach the resumer's trap */
+            ("(def c (coro (fn (x) (signal x)))) \
+              [(trap (fn (x) (resume c x)) [`no] (fn (e) e)) (status c)]",
+             Ok("[|`no, `dead]")),
+            /* but a coroutine can't yield from under a builtin of its own */
+            ("(def c (coro (fn (x) (' (fn (y) (yield y)) x)))) \
+              (resume c [1 2])",
+             Err("yield")),
+        ] {
+            let src = format!("{defs} {src}");
+            let v = or_fatal(lisp::read_all(&src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.unit("main", &v));
+            let mut vm = VM::new(m.program());
+            match (vm.exe_block(b), e) {
+                (Ok(r), Ok(e)) => assert_eq!(vm.fmt(&r), e, "{src}"),
+                (Err(r), Err(e)) => assert_eq!(r.kind(), e, "{src}"),
+                (r, _) => panic!("{src}: {r:?}"),
+            }
+            assert!(vm.resumers.is_empty(), "{src}");
+            assert_eq!(vm.natives, 0, "{src}");
+        }
+
+        /* a suspended coroutine's stack keeps its tables alive */
+        let v = or_fatal(lisp::read_all(
+            "(def c (coro (fn (x) (at [[x x] (yield [x])] 0)))) \
+             (resume c 1) nil",
+        ));
+        let mut m = Machine::new();
+        let b = or_fatal(m.unit("main", &v));
+        let mut vm = VM::new(m.program());
+        or_fatal(vm.exe_block(b));
+        assert_eq!(vm.status(0).ok(), Some(Status::Suspended));
+        assert!(vm.gc() > 0);
+        let v = vm.get_tables().values().map(|t| t.vec()).collect::<Vec<_>>();
+        assert_eq!(v, [&[Obj::U(1), Obj::U(1)]]);
+        assert_eq!(vm.gc(), 0);
+    }
+
+    #[test]
+    fn snapshots() {
+        let unit = |src: &str| {
+            let v = or_fatal(lisp::read_all(src));
+            let mut m = Machine::new();
+            let b = or_fatal(m.unit("main", &v));
+            (m, b)
+        };
+        let kind = |r: Result<_, Error>| r.map_err(|e| e.kind());
+
+        let (m, b) = unit(
+            "(def v [1 2 3]) (def s `hello) \
+             (def c (coro (fn (x) (+ x (yield x))))) (resume c 1) \
+             (def a (yield `wait)) [(+ a (# v)) s (resume c a)]",
+        );
+        let mut vm = VM::new(m.program());
+        let r = or_fatal(vm.start(b));
+        assert_eq!(vm.fmt(&r.value()), "`wait");
+        let snap = or_fatal(vm.snapshot());
+
+        /* a fresh vm picks up where the old one was */
+        let mut new = VM::new(m.program());
+        assert!(!new.suspended());
+        or_fatal(new.restore(&snap));
+        assert!(new.suspended());
+        assert_eq!(or_fatal(new.snapshot()), snap);
+        for vm in [&mut vm, &mut new] {
+            let Run::Finished(r) = or_fatal(vm.resume(Obj::U(10))) else {
+                panic!("still suspended");
+            };
+            assert_eq!(vm.fmt(&r), "[|13, `hello, 11]");
+        }
+
+        /* and a finished one can go back */
+        or_fatal(new.restore(&snap));
+        let r = or_fatal(new.resume(Obj::U(0)));
+        assert_eq!(new.fmt(&r.value()), "[|3, `hello, 1]");
+
+        let (other, _) = unit("(yield 1)");
+        let mut vm = VM::new(other.program());
+        assert_eq!(kind(vm.restore(&snap)), Err("snapshot"));
+
+        /* symbol ids depend on what the program interned first, so the
+         * fingerprint goes by names */
+        let mut m = Machine::new();
+        m.syms.intern("fpz");
+        let v = or_fatal(lisp::read_all("(yield [`fpa `fpz])"));
+        let b = or_fatal(m.unit("main", &v));
+        let mut vm = VM::new(m.program());
+        or_fatal(vm.start(b));
+        let snap = or_fatal(vm.snapshot());
+        assert_eq!(snap[4..12], 0x477d828f1da04af5u64.to_le_bytes());
+        or_fatal(VM::new(m.program()).restore(&snap));
+
+        /* names the program doesn't have aren't made up on restore */
+        let i = snap.windows(3).position(|x| x == b"fpa").unwrap();
+        let mut odd = snap.clone();
+        odd[i + 2] = b'q';
+        let mut vm = VM::new(m.program());
+        for b in [
+            &snap[..snap.len() - 1],
+            &[snap.as_slice(), &[0]].concat(),
+            &snap[1..],
+            &[],
+            &odd,
+        ] {
+            assert_eq!(kind(vm.restore(b)), Err("snapshot"));
+            assert!(!vm.suspended());
+            assert!(vm.get_tables().is_empty());
+        }
+        assert_eq!(vm.program().syms.get("fpq"), None);
+    }
+
+    #[test]
+    fn linker() {
+        let lib = or_fatal(lisp::read_all(
+            "(def n (! 3)) (export inc (fn (x) (+ 1 x))) (def two 2)",
+        ));
+        let main = or_fatal(lisp::read_all(
+            "(def v (! (+ 1 2))) (import math inc) [(@ inc 41) v]",
+        ));
+
+        /* each unit is compiled on its own, so both start at 0 */
+        let mut a = Machine::new();
+        or_fatal(a.unit("math", &lib));
+        let mut b = Machine::new();
+        or_fatal(b.unit("main", &main));
+        assert_eq!(b.externs.len(), 1);
+
+        let m = or_fatal(link(&[&a, &b]));
+        m.dump();
+        assert!(m.externs.is_empty());
+        assert_eq!(m.units["math"].blk, a.units["math"].blk);
+        assert_eq!(m.units["main"].blk, a.blocks.len() + b.units["main"].blk);
+
+        let mut vm = VM::new(m.program());
+        or_fatal(vm.exe_block(m.units["math"].blk));
+        let r = or_fatal(vm.exe_block(m.units["main"].blk));
+        assert_eq!(vm.fmt(&r), "[|42, [|0, 1, 2]]");
+
+        /* imports that don't resolve fail at link time */
+        for (src, e) in [
+            ("(import math two)", is!(Private { .. })),
+            ("(import math three)", is!(Missing { .. })),
+            ("(import maths inc)", is!(Compile { .. })),
+        ] {
+            let v = or_fatal(lisp::read_all(src));
+            let mut b = Machine::new();
+            or_fatal(b.unit("bad", &v));
+            let r = link(&[&a, &b]);
+            assert!(r.as_ref().is_err_and(e), "{src}: {r:?}");
+        }
+        assert!(link(&[&a, &a]).is_err());
+    }
+
+    #[test]
+    fn programs() {
+        /* a vm can live next to the machine feeding it, and run what's
+         * compiled after it was made */
+        struct Repl {
+            m: Machine,
+            vm: VM,
+        }
+        let mut r = Repl {
+            m: Machine::new(),
+            vm: VM::new(Program::default()),
+        };
+        for (i, (src, e)) in [
+            ("(export x 20)", "20"),
+            ("(import l0 x) (export f (fn (y) (+ x y)))", "{&"),
+            ("(import l1 f) (def y (yield 0n)) (@ f y)", "0n"),
         ]
         .into_iter()
         .enumerate()
         {
-            println!(" === TEST {i} ===");
-            println!("{x:?}");
-            let mut m = Machine::new();
-            let b = or_fatal(m.compile(&x));
-            m.dump();
-            println!("beginning execution at block {b}");
-
-            let mut vm = VM::new(&m.instrs, &m.blocks, &m.bodies);
-            let e = or_fatal(vm.exe_block(b));
+            let v = or_fatal(lisp::read_all(src));
+            let b = or_fatal(r.m.unit(&format!("l{i}"), &v));
+            let old = r.vm.program().clone();
+            or_fatal(r.vm.load(r.m.program()));
+            assert!(r.vm.program().extends(&old));
+            let x = or_fatal(r.vm.start(b)).value();
+            assert!(r.vm.fmt(&x).starts_with(e), "{src}: {x:?}");
+        }
 
-            println!("tables:");
-            vm.dump_tables();
+        /* a suspended script carries on in a program that's grown */
+        let v = or_fatal(lisp::read_all("(+ 1 2)"));
+        let b = or_fatal(r.m.unit("l3", &v));
+        or_fatal(r.vm.load(r.m.program()));
+        let x = or_fatal(r.vm.resume(Obj::U(22)));
+        assert_eq!(x, Run::Finished(Obj::U(42)));
+        assert_eq!(or_fatal(r.vm.start(b)), Run::Finished(Obj::U(3)));
 
-            assert_eq!(vm.fmt(&e), y.to_string())
+        /* reloading over and over keeps extending what the vm has, and
+         * the source each reload was read from can go */
+        for i in 0..50 {
+            let src = format!("(export n{i} {i})");
+            let v = or_fatal(lisp::read_all(&src));
+            let b = or_fatal(r.m.unit(&format!("n{i}"), &v));
+            drop(v);
+            drop(src);
+            let old = r.vm.program().clone();
+            or_fatal(r.vm.load(r.m.program()));
+            assert!(r.vm.program().extends(&old));
+            assert_eq!(or_fatal(r.vm.start(b)), Run::Finished(Obj::U(i)));
         }
+        let e = r.m.exports("n49");
+        assert_eq!(e, Some(vec![("n49".into(), ObjType::U)]));
+
+        /* clones share one copy */
+        let p = r.m.program();
+        let vm = VM::new(p.clone());
+        assert!(std::ptr::eq(vm.code(), p.instrs.as_slice()));
+
+        /* and the vm's stays as it was while the machine's grows */
+        or_fatal(r.m.unit("l4", &v));
+        assert_eq!(vm.code().len(), p.instrs.len());
+        assert!(r.m.program().extends(vm.program()));
+
+        /* a clone of the machine goes its own way from the same code */
+        let fork = r.m.clone();
+        assert_eq!(fork.instrs.len(), r.m.instrs.len());
+        assert!(!fork.program().extends(&r.m.program()));
+        assert!(!r.m.program().extends(&fork.program()));
+
+        /* but a program that doesn't extend the vm's can't replace it */
+        let mut other = Machine::new();
+        let v = or_fatal(lisp::read_all("(+ 1 2)"));
+        or_fatal(other.unit("main", &v));
+        let r = r.vm.load(other.program());
+        assert!(matches!(r, Err(Error::Reload { .. })), "{r:?}");
+        let mut vm = VM::new(other.program());
+        assert!(vm.load(Program::default()).is_err());
+        or_fatal(vm.load(other.program()));
     }
 }